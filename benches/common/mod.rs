@@ -0,0 +1,20 @@
+//! What the benchmarks share.
+
+/// A bank of questions with base64 attachments, like the big ones out there.
+pub fn question_bank(questions: usize) -> String {
+	let attachment: String = "QUJDREVGR0hJSktMTU5PUFFSU1RVVldYWVo=".repeat(60);
+	let mut data: String = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<quiz>\n".to_string();
+	for i in 0..questions {
+		data.push_str(&format!("<!-- question: {i}  -->
+  <question type=\"some\">
+    <name><text>Question {i}</text></name>
+    <questiontext format=\"html\">
+      <text><![CDATA[<p>Text of question {i}.</p>]]></text>
+      <file name=\"a.png\" path=\"/\" encoding=\"base64\">{attachment}</file>
+    </questiontext>
+  </question>
+"));
+	}
+	data.push_str("</quiz>\n");
+	data
+}
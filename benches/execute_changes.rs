@@ -0,0 +1,61 @@
+//! Timing of registering and executing large batches of changes on a large question bank.
+//!
+//! Run with `cargo bench`. Compares the execution to the old way of rebuilding
+//! the whole document for each change, and checks that the results are identical.
+//! Registration checks each change against its neighbours in the buffer, so its time
+//! should grow with the number of changes and not with its square.
+
+mod common;
+
+use common::question_bank;
+use position_preserving_moodle_question_xml_edit::*;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// How things were done before, one rebuild of the document per change from the end.
+fn rebuild_per_change(content: &str, changes: &[(Range<usize>, String)]) -> String {
+	let mut result: String = content.to_string();
+	for (range, new_content) in changes.iter().rev() {
+		let mut rebuilt: String = result[..range.start].to_string();
+		rebuilt.push_str(new_content);
+		rebuilt.push_str(&result[range.end..]);
+		result = rebuilt;
+	}
+	result
+}
+
+fn main() {
+	for size in [100, 1000, 3000] {
+		let mut parser = QParser::from_string(question_bank(size)).expect("Valid input should not fail");
+		let original: String = parser.get_current_content();
+		let questions: Vec<Question> = parser.find_questions();
+
+		// Rename every question and add a comment after each, the same changes
+		// in document order for the old way.
+		let mut changes: Vec<(Range<usize>, String)> = Vec::new();
+		let start: Instant = Instant::now();
+		for q in &questions {
+			let name: String = format!("Renamed {}", q.index);
+			changes.push((q.name.range(), name.clone()));
+			parser.register_change(Change::new(q.name.clone(), name));
+
+			let comment: String = format!("<!-- checked {} -->", q.index);
+			let end: usize = q.whole_element.range().end;
+			changes.push((end..end, format!("\n  {comment}")));
+			let target: ContentType = ContentType::Element("question".to_string(), q.whole_element.clone(), vec![]);
+			parser.insert_after(&target, comment).expect("Valid target");
+		}
+		let register_time: Duration = start.elapsed();
+
+		let start: Instant = Instant::now();
+		parser.try_execute_changes().expect("Still valid");
+		let execute_time: Duration = start.elapsed();
+
+		let start: Instant = Instant::now();
+		let expected: String = rebuild_per_change(&original, &changes);
+		let old_time: Duration = start.elapsed();
+
+		assert!(parser.get_current_content() == expected, "The results differ!");
+		println!("{size} questions, {} bytes, {} changes: registered in {register_time:?}, executed in {execute_time:?}, rebuilding per change took {old_time:?}.", original.len(), changes.len());
+	}
+}
@@ -0,0 +1,35 @@
+//! Timing of going through a large question bank question by question, changing
+//! something in each before moving to the next one.
+//!
+//! Run with `cargo bench`. Each search only needs to parse the question it targets,
+//! but as each search executes the change registered before it each of those batches
+//! still copies the whole document. Collecting changes into larger batches is cheaper.
+
+mod common;
+
+use common::question_bank;
+use position_preserving_moodle_question_xml_edit::*;
+use std::time::{Duration, Instant};
+
+fn main() {
+	for size in [100, 1000, 3000] {
+		let mut parser = QParser::from_string(question_bank(size)).expect("Valid input should not fail");
+		let data_size: usize = parser.get_current_content().len();
+
+		let start: Instant = Instant::now();
+		for qnum in 0..size {
+			let elements: Vec<ContentType> = parser.get_elements(qnum, vec!["questiontext".to_string()]);
+			if let ContentType::MoodleTextElement(_, _, parts) = &elements[0] {
+				if let ContentType::ElementContent(text) = &parts[0] {
+					let new_text: String = text.content.replace("Text of", "The text of");
+					parser.register_change(Change::new(text.clone(), new_text));
+				}
+			}
+		}
+		parser.try_execute_changes().expect("Still valid");
+		let loop_time: Duration = start.elapsed();
+
+		assert!(parser.get_current_content().matches("The text of").count() == size, "Missed some!");
+		println!("{size} questions, {data_size} bytes: searched and changed one by one in {loop_time:?}.");
+	}
+}
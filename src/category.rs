@@ -0,0 +1,222 @@
+//! The category pseudo-questions, `<question type="category">`-elements that tell
+//! which category the questions after them go to when the file is imported. Also the
+//! operations for moving questions between them, these move the existing lines as they
+//! are so that the diff shows only the move.
+
+use crate::{Change, ContentRef, ContentType, Error, QParser, Question, escape_text, parse_options};
+
+/// Describes a category-element in the document.
+#[derive(Debug, Clone)]
+pub struct Category {
+    /// The index of this category in the document, `Question.category` refers to this.
+    pub index: usize,
+    /// The path of the category e.g. `$course$/top/Some category`, the contents of the
+    /// `<text>`-element inside the `<category>`-element.
+    pub path: ContentRef,
+    /// The contents of the `<text>`-element inside the `<info>`-element, if present.
+    pub info: Option<ContentRef>,
+    /// The contents of the `<idnumber>`-element, if present and not an empty-tag.
+    pub idnumber: Option<ContentRef>,
+    /// Content reference to the whole `<question>`-element.
+    pub whole_element: ContentRef
+}
+
+/// Is this one of the category pseudo-questions.
+pub(crate) fn is_category(node: &roxmltree::Node) -> bool {
+    node.is_element() && node.tag_name().name() == "question" && node.attribute("type") == Some("category")
+}
+
+impl QParser {
+    /// The contents of the element at the end of the path of child-elements, the node
+    /// being from a parse of the whole document.
+    pub(crate) fn _child_content(&self, node: roxmltree::Node, path: &[&str]) -> Option<ContentRef> {
+        let mut current: roxmltree::Node = node;
+        for name in path {
+            current = current.children().find(|n| n.is_element() && n.tag_name().name() == *name)?;
+        }
+        match self._get_element(current, 0).ok()? {
+            ContentType::Element(_, _, parts) => match parts.last() {
+                Some(ContentType::ElementContent(content)) => Some(content.clone()),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    /// Lists the categories in the document, in document order. A question belongs to
+    /// the category that precedes it, see `Question.category`.
+    ///
+    /// Flushes the change-buffer first, errors if the document is broken or a category
+    /// does not name its path.
+    pub fn find_categories(&mut self) -> Result<Vec<Category>, Error> {
+        self.try_execute_changes()?;
+        self._find_categories()
+    }
+
+    /// The categories in the current version, registered changes are left waiting.
+    fn _find_categories(&self) -> Result<Vec<Category>, Error> {
+        let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
+
+        let mut result: Vec<Category> = Vec::new();
+        for node in doc.descendants().filter(is_category) {
+            let path: ContentRef = self._child_content(node, &["category", "text"]).ok_or(Error::MissingElement("category".to_string()))?;
+            result.push(Category {
+                index: result.len(),
+                path,
+                info: self._child_content(node, &["info", "text"]),
+                idnumber: self._child_content(node, &["idnumber"]),
+                whole_element: ContentRef {
+                    content: self.content[node.range()].to_string(),
+                    start: node.range().start,
+                    end: node.range().end,
+                    version_num: self.version_num,
+                    insertion: false
+                }
+            });
+        }
+        Ok(result)
+    }
+
+    /// Registers changes moving the question, and the `<!-- question: N -->`-comment before
+    /// it, to the end of the block of questions following the category-element. Does nothing
+    /// if the question is already in that block.
+    pub fn move_question_to_category(&mut self, question: &Question, category: &Category) -> Result<(), Error> {
+        let whole: ContentRef = self.rebase(&question.whole_element)?;
+        let target: ContentRef = self.rebase(&category.whole_element)?;
+        let anchor: std::ops::Range<usize> = {
+            let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
+            let node = match doc.descendants().find(|n| is_category(n) && n.range() == target.range()) {
+                Some(n) => n,
+                None => {
+                    return Err(Error::InvalidTarget("no such category in the document".to_string()));
+                }
+            };
+            // The block ends at the next category.
+            let mut last = node;
+            for sibling in node.next_siblings().skip(1).filter(|n| n.is_element()) {
+                if is_category(&sibling) {
+                    break;
+                }
+                if sibling.range() == whole.range() {
+                    // Already there.
+                    return Ok(());
+                }
+                last = sibling;
+            }
+            last.range()
+        };
+
+        let start: usize = self.preceding_question_comment(whole.start).unwrap_or(whole.start);
+        let (remove_start, remove_end) = self.line_extended_range(start, whole.end);
+        let (_, anchor_line_end) = self.line_extended_range(anchor.start, anchor.end);
+        let insertion: Change = if (remove_start, remove_end) != (start, whole.end) && anchor_line_end != anchor.end && self.content[..anchor_line_end].ends_with('\n') {
+            // Whole lines, moved as they are.
+            Change::new(self.insertion_point(anchor_line_end), self.content[remove_start..remove_end].to_string())
+        } else {
+            let indent: &str = self.indentation_at(anchor.start).unwrap_or_default();
+            Change::new(self.insertion_point(anchor.end), format!("{}{indent}{}", self.line_ending().as_str(), &self.content[start..whole.end]))
+        };
+        let removal: Change = Change::new(self.content_ref(remove_start, remove_end), String::new());
+
+        self.try_register_change(insertion.clone())?;
+        if let Err(e) = self.try_register_change(removal) {
+            // Not half of a move.
+            if let Some(i) = self.changes.iter().position(|c| *c == insertion) {
+                self.changes.remove(i);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Registers a change adding a new category-element with the given path to the end
+    /// of the document, the questions can then be moved under it. The path is escaped.
+    /// Like in Moodle exports the element gets a `<!-- question: 0  -->` comment before it.
+    pub fn add_category(&mut self, path: &str) -> Result<(), Error> {
+        let (root, unit, comment) = {
+            let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
+            let quiz = doc.root_element();
+            let last = quiz.children().rfind(|n| n.is_element());
+            // Indented like the questions are.
+            let unit: String = self.indentation_unit(last.unwrap_or(quiz));
+            // Moodle does not indent the comments, but if the existing ones are follow them.
+            let comment_indent: String = quiz.children()
+                .filter(|n| n.is_element())
+                .find_map(|q| self.preceding_question_comment(q.range().start))
+                .and_then(|c| self.indentation_at(c))
+                .unwrap_or_default()
+                .to_string();
+            let line_ending: &str = self.line_ending_around(quiz.range().start, quiz.range().end).as_str();
+            let comment: Option<Change> = last.map(|q| Change::new(self.insertion_point(q.range().end), format!("{line_ending}{comment_indent}<!-- question: 0  -->")));
+            (ContentType::Element(quiz.tag_name().name().to_string(), self.content_ref(quiz.range().start, quiz.range().end), vec![]), unit, comment)
+        };
+        let mut xml: String = format!("<question type=\"category\">
+{unit}<category>
+{unit}{unit}<text>{}</text>
+{unit}</category>
+{unit}<info format=\"moodle_auto_format\">
+{unit}{unit}<text></text>
+{unit}</info>
+</question>", escape_text(path));
+        match comment {
+            Some(comment) => {
+                // Goes to the same point before the element.
+                self.try_register_change(comment.clone())?;
+                if let Err(e) = self.insert_as_last_child(&root, xml) {
+                    if let Some(i) = self.changes.iter().position(|c| *c == comment) {
+                        self.changes.remove(i);
+                    }
+                    return Err(e);
+                }
+                Ok(())
+            },
+            None => {
+                // Nothing to follow in an empty document.
+                xml.insert_str(0, "<!-- question: 0  -->\n");
+                self.insert_as_last_child(&root, xml)
+            }
+        }
+    }
+
+    /// Registers changes renaming the category with the given path, and the categories
+    /// under it, e.g. renaming `$course$/top/A` to `$course$/top/X` also turns `$course$/top/A/B`
+    /// to `$course$/top/X/B`. The paths are given as text, CDATA and entities decoded, and
+    /// written the way the old paths were written.
+    /// Returns the number of categories renamed.
+    ///
+    /// The categories are looked for in the current version, changes registered before
+    /// are not executed. If some of them change the same paths that is an error and none
+    /// of the renames get registered.
+    pub fn rename_category(&mut self, old_path: &str, new_path: &str) -> Result<usize, Error> {
+        let prefix: String = format!("{old_path}/");
+        let mut renames: Vec<Change> = Vec::new();
+        for category in self._find_categories()? {
+            let path: String = category.path.decode();
+            let rest: &str = if path == old_path {
+                ""
+            } else if let Some(rest) = path.strip_prefix(&prefix) {
+                rest
+            } else {
+                continue;
+            };
+            let value: String = if rest.is_empty() {
+                new_path.to_string()
+            } else {
+                format!("{new_path}/{rest}")
+            };
+            renames.push(Change::style_preserving_version(category.path, value));
+        }
+        for (i, rename) in renames.iter().enumerate() {
+            if let Err(e) = self.try_register_change(rename.clone()) {
+                // All or nothing.
+                for registered in &renames[..i] {
+                    if let Some(j) = self.changes.iter().position(|c| c == registered) {
+                        self.changes.remove(j);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(renames.len())
+    }
+}
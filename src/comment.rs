@@ -0,0 +1,49 @@
+//! Comments and processing instructions. Moodle exports have a `<!-- question: N -->`
+//! comment before each question and people leave notes in comments, these give access
+//! to them like to any other content. New ones can be added with `insert_before()` and
+//! `insert_after()` and any of them removed with `remove_element()`.
+
+use crate::{ContentType, Error, QParser, parse_options};
+
+impl QParser {
+    /// Describes a comment or processing instruction, None for other nodes. The base is
+    /// like with `_get_element()`.
+    fn _get_comment(&self, node: roxmltree::Node, base: usize) -> Option<ContentType> {
+        let start: usize = node.range().start + base;
+        let end: usize = node.range().end + base;
+        match node.node_type() {
+            roxmltree::NodeType::Comment => {
+                Some(ContentType::Comment(self.content_ref(start, end), self.content_ref(start + 4, end - 3)))
+            },
+            roxmltree::NodeType::PI => {
+                let pi: roxmltree::PI = node.pi().unwrap();
+                // After the target and the whitespace following it.
+                let after_target: usize = start + 2 + pi.target.len();
+                let value_start: usize = match pi.value {
+                    Some(_) => after_target + self.content[after_target..end - 2].len() - self.content[after_target..end - 2].trim_start().len(),
+                    None => end - 2
+                };
+                Some(ContentType::ProcessingInstruction(pi.target.to_string(), self.content_ref(start, end), self.content_ref(value_start, end - 2)))
+            },
+            _ => None
+        }
+    }
+
+    /// The comments and processing instructions inside a singular question, in document
+    /// order. The `<!-- question: N -->`-comment is before the question, not inside it,
+    /// so it is only found by `get_document_comments()`.
+    pub fn get_comments(&mut self, qnum: usize) -> Result<Vec<ContentType>, Error> {
+        self.with_question(qnum, |parser, node, base| {
+            Ok(node.descendants().filter_map(|n| parser._get_comment(n, base)).collect())
+        })
+    }
+
+    /// All the comments and processing instructions in the document, in document order.
+    /// The XML-declaration is not a processing instruction.
+    pub fn get_document_comments(&mut self) -> Result<Vec<ContentType>, Error> {
+        self.try_execute_changes()?;
+        let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
+        Ok(doc.descendants().filter_map(|n| self._get_comment(n, 0)).collect())
+    }
+}
+
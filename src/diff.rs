@@ -0,0 +1,158 @@
+//! Line based unified diffs, for previewing changes before writing them anywhere.
+//!
+//! A plain Myers diff over the lines that differ, the common start and end of the
+//! documents are skipped first as our changes tend to be small compared to the documents.
+
+/// What happened to a line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert
+}
+
+/// The edit script turning lines `a` into lines `b`.
+fn myers(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let n: isize = a.len() as isize;
+    let m: isize = b.len() as isize;
+    let max: isize = n + m;
+    let offset: isize = max + 1;
+    let mut v: Vec<isize> = vec![0; 2 * max as usize + 3];
+    // The furthest reaching paths after each round, only the diagonals -d..=d.
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        let mut k: isize = -d;
+        while k <= d {
+            let i: usize = (k + offset) as usize;
+            let mut x: isize = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y: isize = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            k += 2;
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        let end: usize = (n - m + offset) as usize;
+        if v[end] >= n && (n - m).abs() <= d {
+            break 'search;
+        }
+    }
+
+    // Then walk back.
+    let mut ops: Vec<Op> = Vec::new();
+    let mut x: isize = n;
+    let mut y: isize = m;
+    for d in (1..trace.len() as isize).rev() {
+        let prev: &Vec<isize> = &trace[(d - 1) as usize];
+        let get = |k: isize| prev[(k + d - 1) as usize];
+        let k: isize = x - y;
+        let prev_k: isize = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x: isize = get(prev_k);
+        let prev_y: isize = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if x == prev_x {
+            ops.push(Op::Insert);
+        } else {
+            ops.push(Op::Delete);
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    while x > 0 && y > 0 {
+        ops.push(Op::Equal);
+        x -= 1;
+        y -= 1;
+    }
+    ops.reverse();
+    ops
+}
+
+/// Pushes a line of the diff, marking missing line-breaks at the end of the document.
+fn push_line(out: &mut String, prefix: char, line: &str) {
+    out.push(prefix);
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// A unified diff between the two texts, with the given number of lines of context around
+/// each change. Empty if the texts are identical.
+pub(crate) fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str, context: usize) -> String {
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let prefix: usize = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let max_suffix: usize = a.len().min(b.len()) - prefix;
+    let suffix: usize = a.iter().rev().zip(b.iter().rev()).take(max_suffix).take_while(|(x, y)| x == y).count();
+    if prefix == a.len() && prefix == b.len() {
+        return String::new();
+    }
+
+    let mut ops: Vec<Op> = vec![Op::Equal; prefix];
+    ops.extend(myers(&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]));
+    ops.extend(vec![Op::Equal; suffix]);
+
+    // Positions of each op in both documents.
+    let mut positions: Vec<(usize, usize)> = Vec::with_capacity(ops.len());
+    let (mut ai, mut bi) = (0, 0);
+    for op in &ops {
+        positions.push((ai, bi));
+        match op {
+            Op::Equal => {
+                ai += 1;
+                bi += 1;
+            },
+            Op::Delete => {
+                ai += 1;
+            },
+            Op::Insert => {
+                bi += 1;
+            }
+        }
+    }
+
+    let mut out: String = format!("--- {old_label}\n+++ {new_label}\n");
+    let changed: Vec<usize> = (0..ops.len()).filter(|i| ops[*i] != Op::Equal).collect();
+    let mut i: usize = 0;
+    while i < changed.len() {
+        // Collect changes close enough to share context into one hunk.
+        let mut j: usize = i;
+        while j + 1 < changed.len() && changed[j + 1] - changed[j] <= 2 * context + 1 {
+            j += 1;
+        }
+        let start: usize = changed[i].saturating_sub(context);
+        let end: usize = (changed[j] + context + 1).min(ops.len());
+        let old_count: usize = ops[start..end].iter().filter(|o| **o != Op::Insert).count();
+        let new_count: usize = ops[start..end].iter().filter(|o| **o != Op::Delete).count();
+        let (old_start, new_start) = positions[start];
+        out.push_str(&format!("@@ -{},{} +{},{} @@\n",
+            if old_count > 0 { old_start + 1 } else { old_start }, old_count,
+            if new_count > 0 { new_start + 1 } else { new_start }, new_count));
+        for k in start..end {
+            let (ak, bk) = positions[k];
+            match ops[k] {
+                Op::Equal => push_line(&mut out, ' ', a[ak]),
+                Op::Delete => push_line(&mut out, '-', a[ak]),
+                Op::Insert => push_line(&mut out, '+', b[bk])
+            }
+        }
+        i = j + 1;
+    }
+    out
+}
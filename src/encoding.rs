@@ -0,0 +1,147 @@
+//! The encodings documents can be read from and written back in. Internally everything is
+//! UTF-8 and all positions are positions in that, the original encoding and a possible
+//! byte order mark only matter when reading and writing.
+//!
+//! Old Moodle exports may be in ISO-8859-1 or Windows-1252, those are simple enough to
+//! handle here without pulling in a full encoding library.
+
+use std::borrow::Cow;
+use std::fmt;
+use crate::Error;
+
+/// The byte order mark of UTF-8.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The characters Windows-1252 has at 0x80-0x9F. The five positions it leaves undefined
+/// are mapped to the matching C1 control characters, like browsers do.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}'
+];
+
+/// The encodings we can read and write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// UTF-8, with or without a byte order mark.
+    Utf8,
+    /// ISO-8859-1, also known as Latin-1.
+    Latin1,
+    /// Windows-1252, Latin-1 with printable characters where it has control characters.
+    Windows1252
+}
+
+impl Encoding {
+    /// The name of the encoding as it would be declared in the XML-declaration.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Latin1 => "ISO-8859-1",
+            Encoding::Windows1252 => "windows-1252"
+        }
+    }
+
+    /// Identifies the encoding from a name in an XML-declaration.
+    fn from_name(name: &str) -> Result<Encoding, Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "latin-1" | "l1" => Ok(Encoding::Latin1),
+            "windows-1252" | "cp1252" => Ok(Encoding::Windows1252),
+            _ => Err(Error::UnsupportedEncoding(name.to_string()))
+        }
+    }
+
+    /// Turns the text into bytes of this encoding, or tells where the first character
+    /// that cannot be represented is.
+    pub(crate) fn encode<'a>(&self, text: &'a str) -> Result<Cow<'a, [u8]>, Error> {
+        if *self == Encoding::Utf8 {
+            return Ok(Cow::Borrowed(text.as_bytes()));
+        }
+        let mut result: Vec<u8> = Vec::with_capacity(text.len());
+        for (position, c) in text.char_indices() {
+            let code: u32 = c as u32;
+            let byte: Option<u8> = if code < 0x80 || (0xA0..=0xFF).contains(&code) || (*self == Encoding::Latin1 && code <= 0xFF) {
+                Some(code as u8)
+            } else if *self == Encoding::Windows1252 {
+                WINDOWS_1252.iter().position(|w| *w == c).map(|i| 0x80 + i as u8)
+            } else {
+                None
+            };
+            match byte {
+                Some(b) => result.push(b),
+                None => {
+                    return Err(Error::Unencodable { position, character: c, encoding: *self });
+                }
+            }
+        }
+        Ok(Cow::Owned(result))
+    }
+
+    /// Turns bytes of this encoding into text.
+    fn decode(&self, bytes: Vec<u8>) -> Result<String, Error> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes).map_err(|e| {
+                Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            }),
+            Encoding::Latin1 => Ok(bytes.iter().map(|b| *b as char).collect()),
+            Encoding::Windows1252 => Ok(bytes.iter().map(|b| match b {
+                0x80..=0x9F => WINDOWS_1252[(b - 0x80) as usize],
+                _ => *b as char
+            }).collect())
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The encoding named in the XML-declaration at the start of the bytes, if there is one.
+fn declared_encoding(bytes: &[u8]) -> Option<String> {
+    if !bytes.starts_with(b"<?xml") {
+        return None;
+    }
+    // The declaration is ASCII in all the encodings we deal with.
+    let end: usize = bytes.windows(2).position(|w| w == b"?>")?;
+    let declaration: &str = std::str::from_utf8(&bytes[..end]).ok()?;
+    let rest: &str = declaration[declaration.find("encoding")? + "encoding".len()..].trim_start();
+    let rest: &str = rest.strip_prefix('=')?.trim_start();
+    let quote: char = rest.chars().next()?;
+    if quote != '"' && quote != '\'' {
+        return None;
+    }
+    let value: &str = &rest[1..];
+    Some(value[..value.find(quote)?].to_string())
+}
+
+/// Decodes a document, returns the text and the encoding it was in and whether it had
+/// a byte order mark. Documents without a declared encoding are UTF-8.
+pub(crate) fn decode(mut bytes: Vec<u8>) -> Result<(String, Encoding, bool), Error> {
+    let bom: bool = bytes.starts_with(BOM);
+    if bom {
+        bytes.drain(..BOM.len());
+    }
+    let encoding: Encoding = match declared_encoding(&bytes) {
+        // A byte order mark trumps the declaration.
+        Some(_) if bom => Encoding::Utf8,
+        Some(name) => Encoding::from_name(&name)?,
+        None => Encoding::Utf8
+    };
+    let text: String = encoding.decode(bytes)?;
+    Ok((text, encoding, bom))
+}
+
+/// Encodes a document the way it was when read.
+pub(crate) fn encode(text: &str, encoding: Encoding, bom: bool) -> Result<Cow<'_, [u8]>, Error> {
+    let encoded: Cow<[u8]> = encoding.encode(text)?;
+    if !bom {
+        return Ok(encoded);
+    }
+    let mut result: Vec<u8> = Vec::with_capacity(encoded.len() + BOM.len());
+    result.extend_from_slice(BOM);
+    result.extend_from_slice(&encoded);
+    Ok(Cow::Owned(result))
+}
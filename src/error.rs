@@ -0,0 +1,152 @@
+//! Errors produced by the parser.
+//!
+//! The original API of this library panics or returns plain Strings, the `try_`-prefixed
+//! functions return these instead so that batch processing of large sets of files can
+//! deal with odd files without stopping the whole run.
+
+use std::fmt;
+use crate::{Change, ChangeConflict, Encoding};
+
+/// Everything that can go wrong when using the parser.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file or some other stream failed.
+    Io(std::io::Error),
+    /// The document is not valid XML. roxmltree provides the position of the problem.
+    XmlParse(roxmltree::Error),
+    /// A ContentRef from a search targeting some other version of the document was used,
+    /// and it could not be rebased as the region it targets has been changed since.
+    StaleReference {
+        /// The version the reference was created for.
+        reference_version: usize,
+        /// The version currently held in memory.
+        current_version: usize
+    },
+    /// A change overlaps a change that has already been registered but not yet executed.
+    /// The conflict describes both of them.
+    OverlappingChange(Box<ChangeConflict>),
+    /// A change inside another change could not be composed into it as the outer change
+    /// no longer contains the region the inner one targets.
+    UncomposableChange(Box<ChangeConflict>),
+    /// Tried to access a question that does not exist.
+    QuestionIndexOutOfRange {
+        /// The index that was requested.
+        index: usize,
+        /// The number of questions in the document.
+        count: usize
+    },
+    /// The question exists but is not of the type the operation requires.
+    WrongQuestionType {
+        /// The index of the question.
+        index: usize,
+        /// The type the operation requires.
+        expected: String,
+        /// The type of the question in the document.
+        found: String
+    },
+    /// An element that was required for the operation is missing, names the element.
+    MissingElement(String),
+    /// An element was present but not in the expected form, e.g. a text-field without its format.
+    UnexpectedElement(String),
+    /// The operation cannot act on the given target, describes why.
+    InvalidTarget(String),
+    /// Executing the registered changes would have produced invalid XML, so they were not
+    /// executed. Names the changes that seem to be the cause.
+    BrokenChanges {
+        /// What roxmltree had to say about the result.
+        error: roxmltree::Error,
+        /// The changes that touch the position of the error, or break the document on their own.
+        offending: Vec<Change>
+    },
+    /// A transaction was begun while one was already open, or committed or rolled back
+    /// when none was, describes which.
+    TransactionState(String),
+    /// The operation cannot be done while there are registered changes waiting for execution.
+    PendingChanges,
+    /// The document declares an encoding we cannot read, names it.
+    UnsupportedEncoding(String),
+    /// The content has a character that the encoding of the document cannot represent.
+    Unencodable {
+        /// The position of the character in the content.
+        position: usize,
+        /// The character.
+        character: char,
+        /// The encoding of the document.
+        encoding: Encoding
+    },
+    /// The selector given to `select()` could not be understood, describes why.
+    InvalidSelector(String),
+    /// No question matched a lookup, describes the lookup.
+    NoSuchQuestion(String),
+    /// More than one question matched a lookup that should identify a single question.
+    AmbiguousQuestion {
+        /// Describes the lookup.
+        query: String,
+        /// The indices of the matching questions.
+        indices: Vec<usize>
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "IO error: {e}."),
+            Error::XmlParse(e) => write!(f, "Error parsing the document: {e}."),
+            Error::StaleReference { reference_version, current_version } => {
+                write!(f, "Use of a content-reference to a stale search result detected. Reference is for version {reference_version}, document is at version {current_version}.")
+            },
+            Error::OverlappingChange(conflict) => {
+                write!(f, "Overlap of uncommitted changes, cannot continue. Change to {:?} collides with an earlier change to {:?}.", conflict.incoming.position.range(), conflict.existing.position.range())
+            },
+            Error::UncomposableChange(conflict) => {
+                write!(f, "Cannot compose the change to {:?} into the change to {:?}, the replacement no longer contains that region.", conflict.incoming.position.range(), conflict.existing.position.range())
+            },
+            Error::QuestionIndexOutOfRange { index, count } => {
+                write!(f, "Only {count} questions, but was trying to get index {index}.")
+            },
+            Error::WrongQuestionType { index, expected, found } => {
+                write!(f, "Was expecting a '{expected}' question at index {index}, found '{found}' instead.")
+            },
+            Error::MissingElement(name) => write!(f, "Missing '{name}' element."),
+            Error::UnexpectedElement(name) => write!(f, "Unexpected form of the '{name}' element."),
+            Error::InvalidTarget(reason) => write!(f, "Invalid target: {reason}."),
+            Error::BrokenChanges { error, offending } => {
+                write!(f, "The changes would break the document and were not executed: {error}. {} offending change(s).", offending.len())
+            },
+            Error::TransactionState(reason) => write!(f, "Transaction error: {reason}."),
+            Error::PendingChanges => write!(f, "There are registered changes waiting for execution, execute or discard them first."),
+            Error::UnsupportedEncoding(name) => write!(f, "Unsupported encoding '{name}'."),
+            Error::Unencodable { position, character, encoding } => {
+                write!(f, "Character {character:?} at {position} cannot be represented in {encoding}.")
+            },
+            Error::InvalidSelector(reason) => write!(f, "Invalid selector: {reason}."),
+            Error::NoSuchQuestion(query) => write!(f, "No question with {query}."),
+            Error::AmbiguousQuestion { query, indices } => {
+                write!(f, "Multiple questions with {query}, at indices {indices:?}.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::XmlParse(e) => Some(e),
+            Error::BrokenChanges { error, .. } => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<roxmltree::Error> for Error {
+    fn from(e: roxmltree::Error) -> Error {
+        Error::XmlParse(e)
+    }
+}
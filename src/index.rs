@@ -0,0 +1,152 @@
+//! An index of where the questions are in the current version of the document, so that
+//! searches targeting a single question only need to parse that question.
+//!
+//! The index is built from a parse of the whole document and then moved along with
+//! the executed changes, as long as those changes stay inside the questions. Anything
+//! else, like adding or removing questions, means that the next search builds it again.
+
+use crate::{Edit, Error, QParser, find_question_node, parse_options};
+
+/// Where a question is in a version of the document.
+#[derive(Debug, Clone)]
+pub(crate) struct IndexedQuestion {
+    /// The start of the whole element.
+    pub(crate) start: usize,
+    /// The end of the whole element.
+    pub(crate) end: usize,
+    /// The region between the start- and end-tags, None for empty-tags.
+    inner: Option<(usize, usize)>
+}
+
+/// The questions of a version, in the order `find_questions()` lists them.
+#[derive(Debug, Clone)]
+pub(crate) struct QuestionIndex {
+    pub(crate) version_num: usize,
+    pub(crate) questions: Vec<IndexedQuestion>
+}
+
+/// Lists the questions of a parsed document, the same ones `find_question_node()` counts.
+pub(crate) fn index_questions(doc: &roxmltree::Document, content: &str) -> Vec<IndexedQuestion> {
+    let mut result: Vec<IndexedQuestion> = Vec::new();
+    for node in doc.descendants() {
+        if node.is_element() && node.tag_name().name() == "question" {
+            match node.attribute("type") {
+                Some("category") | None => {
+                    // Not counted.
+                },
+                Some(_) => {
+                    let start: usize = node.range().start;
+                    let end: usize = node.range().end;
+                    let inner: Option<(usize, usize)> = if content[..end].ends_with("/>") {
+                        None
+                    } else {
+                        // Attribute values may contain '>' so look only after them.
+                        let after_attributes: usize = node.attributes().next_back().map(|a| a.range().end).unwrap_or(start);
+                        let inner_start: usize = after_attributes + content[after_attributes..].find('>').unwrap() + 1;
+                        let inner_end: usize = content[..end].rfind("</").unwrap();
+                        Some((inner_start, inner_end))
+                    };
+                    result.push(IndexedQuestion {
+                        start,
+                        end,
+                        inner
+                    });
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Moves a position by the given amount.
+fn shifted(pos: usize, shift: isize) -> usize {
+    (pos as isize + shift) as usize
+}
+
+impl QuestionIndex {
+    /// The index after the given edits, sorted by position, if all of them stay inside
+    /// questions and do not deal with question-elements. Also returns the indices of
+    /// the questions that were edited.
+    pub(crate) fn rebase(&self, edits: &[Edit], texts: &[(String, String)]) -> Option<(Vec<IndexedQuestion>, Vec<usize>)> {
+        let mut questions: Vec<IndexedQuestion> = Vec::with_capacity(self.questions.len());
+        let mut touched: Vec<usize> = Vec::new();
+        let mut i: usize = 0;
+        let mut shift: isize = 0;
+        let mut previous_end: usize = 0;
+        for (qn, q) in self.questions.iter().enumerate() {
+            if q.start < previous_end {
+                // Questions inside questions, not worth the trouble.
+                return None;
+            }
+            previous_end = q.end;
+            let mut growth: isize = 0;
+            let first_edit: usize = i;
+            while i < edits.len() && edits[i].start < q.end {
+                let edit: &Edit = &edits[i];
+                let inside: bool = match q.inner {
+                    Some((a, b)) => a <= edit.start && edit.end <= b,
+                    None => false
+                };
+                if !inside || texts[i].0.contains("<question") || texts[i].1.contains("<question") {
+                    return None;
+                }
+                growth = growth + edit.new_len as isize - (edit.end - edit.start) as isize;
+                i += 1;
+            }
+            if i > first_edit {
+                touched.push(qn);
+            }
+            questions.push(IndexedQuestion {
+                start: shifted(q.start, shift),
+                end: shifted(q.end, shift + growth),
+                inner: q.inner.map(|(a, b)| (shifted(a, shift), shifted(b, shift + growth)))
+            });
+            shift += growth;
+        }
+        if i < edits.len() {
+            // Something after the last question.
+            return None;
+        }
+        Some((questions, touched))
+    }
+}
+
+impl QParser {
+    /// Makes sure that there is an index for the current version.
+    pub(crate) fn ensure_index(&mut self) -> Result<(), Error> {
+        if let Some(index) = &self.index {
+            if index.version_num == self.version_num {
+                return Ok(());
+            }
+        }
+        let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
+        let questions: Vec<IndexedQuestion> = index_questions(&doc, &self.content);
+        self.index = Some(QuestionIndex {
+            version_num: self.version_num,
+            questions
+        });
+        Ok(())
+    }
+
+    /// Executes the pending changes and gives the `<question>`-element with the given
+    /// index to the function. Only that question gets parsed, unless it cannot be parsed
+    /// on its own e.g. due to entities declared in the DTD. The function also gets where
+    /// the parsed text starts in the content, the positions of the nodes are relative to it.
+    pub(crate) fn with_question<T>(&mut self, qnum: usize, f: impl FnOnce(&QParser, roxmltree::Node, usize) -> Result<T, Error>) -> Result<T, Error> {
+        self.try_execute_changes()?;
+        self.ensure_index()?;
+        let questions: &Vec<IndexedQuestion> = &self.index.as_ref().unwrap().questions;
+        let (start, end) = match questions.get(qnum) {
+            Some(q) => (q.start, q.end),
+            None => {
+                return Err(Error::QuestionIndexOutOfRange { index: qnum, count: questions.len() });
+            }
+        };
+        if let Ok(doc) = roxmltree::Document::parse_with_options(&self.content[start..end], parse_options()) {
+            return f(self, doc.root_element(), start);
+        }
+        let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
+        let node = find_question_node(&doc, qnum)?;
+        f(self, node, 0)
+    }
+}
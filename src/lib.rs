@@ -14,6 +14,33 @@
 
 // Some extra question type specific structs are in other files.
 pub mod stack;
+// As are the errors.
+mod error;
+pub use error::Error;
+// And the operations modifying the structure of the document.
+mod structure;
+// Previews of changes.
+mod diff;
+// Where the questions are.
+mod index;
+// Saving files.
+mod save;
+pub use save::{SaveOptions, SaveOutcome};
+// Reading and writing other encodings than UTF-8.
+mod encoding;
+pub use encoding::Encoding;
+// Selecting elements by path.
+mod selector;
+// The categories.
+mod category;
+pub use category::Category;
+// Finding questions by their names and ids.
+mod lookup;
+// Comments and processing instructions.
+mod comment;
+// Lines and columns.
+mod location;
+pub use location::{Location, TextPosition};
 
 /// References to values in content.
 #[derive(Debug, PartialEq, Clone)]
@@ -25,10 +52,21 @@ pub struct ContentRef {
     /// And the matching end
     end: usize,
     /// During which search were these valid.
-    version_num: usize
+    version_num: usize,
+    /// Is this a point the structural operations insert at rather than some content.
+    /// Changes at the same such point are executed in order instead of conflicting,
+    /// for any empty reference touching the edge of a change see `overlaps()`.
+    insertion: bool
 }
 impl ContentRef {
+    /// The byte range in the document this reference points to.
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start..self.end
+    }
+
     /// When accessing content that might be CDATA wrapped one might want it unwrapped.
+    /// Handles multiple sections, including those split to contain `]]>` as
+    /// `strict_cdata_wrapped_version()` splits them.
     pub fn unwrap_cdata(&self) -> String {
         if self.content.contains("<![CDATA[") {
             // The silliest of parsers, but avoids doing anything extra.
@@ -44,13 +82,13 @@ impl ContentRef {
                             out.push_str(&"]]>"[..cdata_end]);
                             cdata_end = 0;
                         }
-                        if !in_cdata && cdata_start == 0 {
-                            cdata_start = 1;
-                        } else {
+                        if !in_cdata {
+                            // Any partial start was not one, but this may be.
                             if cdata_start > 0 {
                                 out.push_str(&"<![CDATA["[..cdata_start]);
-                                cdata_start = 0;
                             }
+                            cdata_start = 1;
+                        } else {
                             out.push(c);
                         }
                     },
@@ -220,6 +258,94 @@ impl ContentRef {
             .replace("&#xD;","\r")
             .replace("&amp;","&")
     }
+
+    /// The text value of the content as an XML parser would see it. Decodes the predefined
+    /// entities and numeric character references, unwraps CDATA sections, and normalises
+    /// line-breaks to `\n`, all in one pass so that mixed content comes out right. Entities
+    /// declared in a DTD and any markup are left as they are.
+    pub fn decode(&self) -> String {
+        let content: &str = &self.content;
+        let mut out: String = String::with_capacity(content.len());
+        let mut pos: usize = 0;
+        while pos < content.len() {
+            let rest: &str = &content[pos..];
+            if let Some(inner) = rest.strip_prefix("<![CDATA[") {
+                let length: usize = inner.find("]]>").unwrap_or(inner.len());
+                push_normalized(&mut out, &inner[..length]);
+                pos = pos + "<![CDATA[".len() + (length + 3).min(inner.len());
+            } else if rest.starts_with('&') {
+                match rest.find(';').and_then(|end| decode_reference(&rest[1..end]).map(|c| (c, end))) {
+                    Some((c, end)) => {
+                        out.push(c);
+                        pos = pos + end + 1;
+                    },
+                    None => {
+                        out.push('&');
+                        pos += 1;
+                    }
+                }
+            } else {
+                // At least the first character, it may be a '<' of some markup.
+                let first: usize = rest.chars().next().unwrap().len_utf8();
+                let length: usize = rest[first..].find(['<', '&']).map(|i| i + first).unwrap_or(rest.len());
+                push_normalized(&mut out, &rest[..length]);
+                pos += length;
+            }
+        }
+        out
+    }
+}
+
+/// Appends text with its `\r\n` and lone `\r` line-breaks turned to `\n`.
+fn push_normalized(out: &mut String, text: &str) {
+    if text.contains('\r') {
+        out.push_str(&text.replace("\r\n", "\n").replace('\r', "\n"));
+    } else {
+        out.push_str(text);
+    }
+}
+
+/// The character an entity or character reference stands for, given what is between
+/// the `&` and the `;`. None for anything else.
+fn decode_reference(reference: &str) -> Option<char> {
+    match reference {
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "amp" => Some('&'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let code: u32 = if let Some(hex) = reference.strip_prefix("#x") {
+                u32::from_str_radix(hex, 16).ok()?
+            } else if let Some(decimal) = reference.strip_prefix('#') {
+                decimal.parse::<u32>().ok()?
+            } else {
+                return None;
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// Wraps the text in CDATA, splitting the section wherever the text has `]]>`.
+fn cdata_wrap(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// Escapes text for use as an attribute value, quoted either way.
+pub(crate) fn escape_attribute(text: &str) -> String {
+    text.replace("&","&amp;")
+        .replace("<","&lt;")
+        .replace(">","&gt;")
+        .replace("\"","&quot;")
+        .replace("'","&apos;")
+        .replace("\n","&#xA;")
+        .replace("\r","&#xD;")
+}
+
+/// Escapes text for use as element content.
+pub(crate) fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
 }
 
 
@@ -227,7 +353,7 @@ impl ContentRef {
 /// Some types provide more, details and related objects.
 ///
 /// Do note that when a type provides access to parts of itself you must not change the whole and its parts
-/// at the same time.
+/// at the same time. Unless you register those changes with `ConflictPolicy::ComposeNested`.
 #[derive(Debug, Clone)]
 pub enum ContentType {
     /// Name of an atribute and a refrence to the content inside those quotes '' or "".
@@ -237,19 +363,19 @@ pub enum ContentType {
     /// Name of an element, a reference to the whole element, as well as a list of the attributes with the last element being the content of the element.
     Element(String, ContentRef, Vec<ContentType>),
     /// The tagname, reference to the format and as the first item in the list the contents of the text-element and the rest of the items are attachments as elements.
-    MoodleTextElement(String, ContentRef, Vec<ContentType>)
+    MoodleTextElement(String, ContentRef, Vec<ContentType>),
+    /// A reference to the whole `<!-- comment -->` and a reference to the text inside it.
+    Comment(ContentRef, ContentRef),
+    /// The target of a `<?target value?>` processing instruction, a reference to the whole of it and a reference to its value.
+    ProcessingInstruction(String, ContentRef, ContentRef)
 }
 impl ContentType {
     /// If this is an element or attribute and the attribute requested 
     /// is available return a ref to it.
     pub fn get_attr(self, name: String) -> Option<ContentRef> {
         match self {
-            ContentType::AttributeValue(aname, value) => {
-                if aname == name {
-                    Some(value)
-                } else {
-                    None
-                }
+            ContentType::AttributeValue(aname, value) if aname == name => {
+                Some(value)
             },
             ContentType::Element(_, _, attributes_and_value) => {
                 for item in attributes_and_value {
@@ -261,12 +387,8 @@ impl ContentType {
                 }
                 None
             },
-            ContentType::MoodleTextElement(_, format, _) => {
-                if name == "format".to_string() {
-                    Some(format)
-                } else {
-                    None
-                }
+            ContentType::MoodleTextElement(_, format, _) if name == "format" => {
+                Some(format)
             },
             _ => {
                 None
@@ -279,6 +401,8 @@ impl ContentType {
     ///  - Element -> the content unwrapped from ElementContent, if this is an `<empty/>`-tag then None.
     ///  - ElementContent -> the content
     ///  - MoodleTextElement -> the content of the `<text>`-element.
+    ///  - Comment -> the text inside the comment.
+    ///  - ProcessingInstruction -> the value.
     pub fn get_content(self) -> Option<ContentRef> {
         match self {
             ContentType::AttributeValue(_, value) => {
@@ -296,6 +420,12 @@ impl ContentType {
                 if let ContentType::ElementContent(content) = content_and_files.first().unwrap() {
                     return Some(content.clone());
                 }
+            },
+            ContentType::Comment(_, text) => {
+                return Some(text);
+            },
+            ContentType::ProcessingInstruction(_, _, value) => {
+                return Some(value);
             }
         }
         None
@@ -313,10 +443,60 @@ pub struct Question {
     /// Name element contents.
     pub name: ContentRef,
     /// Content reference ot the whole `<question>`-element. For when you want to copy or remove the whole question from the document.
-    pub whole_element: ContentRef
+    pub whole_element: ContentRef,
+    /// The index of the category this question belongs to, i.e. the closest category-element
+    /// before it, in the list `find_categories()` gives. None if there is no such category.
+    pub category: Option<usize>,
+    /// The contents of the `<idnumber>`-element, if present and not an empty-tag.
+    pub idnumber: Option<ContentRef>,
+    /// The id of the question in the Moodle it was exported from, from the
+    /// `<!-- question: N -->`-comment before it.
+    pub comment_id: Option<u64>
+}
+
+/// The styles of line-breaks found in question XML.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+    /// `\r\n`, common in content written on Windows and in Moodle textareas.
+    CrLf
+}
+impl LineEnding {
+    /// The style most of the line-breaks in the text use, None if there are none.
+    /// Ties go to `\n`.
+    pub fn detect(text: &str) -> Option<LineEnding> {
+        let crlf: usize = text.matches("\r\n").count();
+        let lf: usize = text.matches('\n').count() - crlf;
+        if crlf == 0 && lf == 0 {
+            None
+        } else if crlf > lf {
+            Some(LineEnding::CrLf)
+        } else {
+            Some(LineEnding::Lf)
+        }
+    }
+
+    /// The line-break itself.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n"
+        }
+    }
+
+    /// Turns all line-breaks in the text, lone `\r`s included, to this style.
+    pub fn normalize(&self, text: &str) -> String {
+        let unified: String = text.replace("\r\n", "\n").replace('\r', "\n");
+        match self {
+            LineEnding::Lf => unified,
+            LineEnding::CrLf => unified.replace('\n', "\r\n")
+        }
+    }
 }
 
 /// A change to be executed.
+#[derive(Debug, PartialEq, Clone)]
 pub struct Change {
     /// The position that will change.
     pub position: ContentRef,
@@ -326,16 +506,9 @@ pub struct Change {
 impl Change {
     /// Create a Change, entity escaped version of the given value. For when modifying attribute values.
     pub fn attribute_escaped_version(position: ContentRef, value: String) -> Change {
-        let escaped: String = value.replace("&","&amp;")
-            .replace("<","&lt;")
-            .replace(">","&gt;")
-            .replace("\"","&quot;")
-            .replace("'","&apos;")
-            .replace("\n","&#xA;")
-            .replace("\r","&#xD;");
         Change {
-            position: position,
-            new_content: escaped
+            position,
+            new_content: escape_attribute(&value)
         }
     }
 
@@ -344,83 +517,438 @@ impl Change {
     /// Note that this is not a proper CDATA escape dealing with CDATA parts inside CDATA, 
     /// it matches the Moodle one used in for example STACK: 
     /// <https://github.com/moodle/moodle/blob/d7bb4636df0cdb40b7eb6af32abb4ee6615fc78f/question/format/xml/format.php#L1027>
+    /// Values containing `]]>` will break the document, see `strict_cdata_wrapped_version()`.
     pub fn cdata_wrapped_version(position: ContentRef, value: String) -> Change {
         // https://www.php.net/manual/en/function.htmlspecialchars.php
         if value.contains("&") || value.contains("\"") || value.contains("'") || value.contains("<") || value.contains(">") {
             Change {
-                position: position,
+                position,
                 new_content: format!("<![CDATA[{value}]]>")
             }
         } else {
             Change {
-                position: position,
+                position,
                 new_content: value
             }
         }
     }
 
-    /// Just create a Change struct
+    /// Create a Change, with the line-breaks of the value matching those of the content
+    /// being replaced. If that content has no line-breaks the given default is used,
+    /// e.g. `QParser::line_ending()` to match the document.
+    pub fn line_ending_normalized_version(position: ContentRef, value: String, default: LineEnding) -> Change {
+        let line_ending: LineEnding = LineEnding::detect(&position.content).unwrap_or(default);
+        Change {
+            new_content: line_ending.normalize(&value),
+            position
+        }
+    }
+
+    /// Create a Change, entity escaped version of the given value. For when modifying the
+    /// content of elements without wrapping it in CDATA, quotes and line-breaks are kept
+    /// as they are. The reverse of `ContentRef::decode()`.
+    pub fn text_escaped_version(position: ContentRef, value: String) -> Change {
+        Change {
+            position,
+            new_content: escape_text(&value)
+        }
+    }
+
+    /// Create a Change, and CDATA wrap the content if need be, like `cdata_wrapped_version()`
+    /// but correctly. Any `]]>` in the value would end the CDATA section, so the section
+    /// gets split between the `]]` and the `>`, which parsers read back as the original value.
+    /// Use this when the value may contain `]]>`, e.g. CASText with `a[[1]]>0`.
+    pub fn strict_cdata_wrapped_version(position: ContentRef, value: String) -> Change {
+        if value.contains("&") || value.contains("\"") || value.contains("'") || value.contains("<") || value.contains(">") {
+            Change {
+                position,
+                new_content: cdata_wrap(&value)
+            }
+        } else {
+            Change {
+                position,
+                new_content: value
+            }
+        }
+    }
+
+    /// Create a Change, encoding the value the same way the content being replaced was
+    /// encoded, so that the diff does not flip between styles. If that content had CDATA
+    /// the value gets CDATA wrapped, if it had entities it gets entity escaped and if it
+    /// was plain the Moodle rule of `cdata_wrapped_version()` is used. CDATA sections get
+    /// split like `strict_cdata_wrapped_version()` splits them.
+    pub fn style_preserving_version(position: ContentRef, value: String) -> Change {
+        if position.content.contains("<![CDATA[") {
+            Change {
+                position,
+                new_content: cdata_wrap(&value)
+            }
+        } else if position.content.contains('&') {
+            Change::text_escaped_version(position, value)
+        } else {
+            Change::strict_cdata_wrapped_version(position, value)
+        }
+    }
+
+    /// Create a Change, with the value made safe for use as the text of a comment. Comments
+    /// cannot contain `--` nor end with `-`, so those get separated with spaces.
+    pub fn comment_escaped_version(position: ContentRef, value: String) -> Change {
+        let mut escaped: String = value;
+        while escaped.contains("--") {
+            escaped = escaped.replace("--", "- -");
+        }
+        if escaped.ends_with('-') {
+            escaped.push(' ');
+        }
+        Change {
+            position,
+            new_content: escaped
+        }
+    }
+
+    /// Just create a Change struct, the value is used as is.
     pub fn new(position: ContentRef, value: String) -> Change {
         Change {
-            position: position,
+            position,
             new_content: value
         }
     }
 }
 
 
+
+
+/// Do these two references overlap in a way that would make executing changes 
+/// targeting both of them ambiguous.
+///
+/// Non-empty references overlap if they share any content. An empty reference overlaps
+/// one strictly around it, but not one it touches at the start or end, those changes
+/// are just executed in order. Up to 0.1.2 an empty reference at the start of another
+/// one overlapped. Two empty references at the same position overlap unless both are
+/// insertion points.
+fn overlaps(a: &ContentRef, b: &ContentRef) -> bool {
+    // Two changes to the same empty content, unless both just insert there.
+    if a.start == a.end && b.start == b.end && a.start == b.start {
+        return !(a.insertion && b.insertion);
+    }
+    // Insertions only overlap if they are strictly inside, at the edges they are ordered.
+    if a.start == a.end {
+        return b.start < a.start && a.start < b.end;
+    } else if b.start == b.end {
+        return a.start < b.start && b.start < a.end;
+    }
+    // Is a inside b?
+    if (a.start < b.end && a.start >= b.start) || (a.end <= b.end && a.end > b.start) {
+        return true;
+    }
+    // Is b inside a?
+    (b.start < a.end && b.start >= a.start) || (b.end <= a.end && b.end > a.start)
+}
+
+/// Is the inner reference inside the outer one, and not the same range.
+fn contains(outer: &ContentRef, inner: &ContentRef) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end && (outer.start, outer.end) != (inner.start, inner.end)
+}
+
+/// Applies a change targeting a region inside the outer change to the new content of
+/// the outer change. Returns None if the region cannot be identified in the new content.
+///
+/// Whatever is identical at the start and end of the original and the new content of the 
+/// outer change is considered to be the same content, in the middle we accept the region 
+/// if its original content appears exactly once in the new content.
+fn compose(content: &str, outer: &Change, inner: &Change) -> Option<String> {
+    let original: &str = &content[outer.position.start..outer.position.end];
+    let replacement: &str = &outer.new_content;
+    let a: usize = inner.position.start - outer.position.start;
+    let b: usize = inner.position.end - outer.position.start;
+
+    // Common prefix and suffix, on char boundaries.
+    let mut prefix: usize = original.bytes().zip(replacement.bytes()).take_while(|(x, y)| x == y).count();
+    while !original.is_char_boundary(prefix) || !replacement.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    let max_suffix: usize = original.len().min(replacement.len()) - prefix;
+    let suffix: usize = original.bytes().rev().zip(replacement.bytes().rev()).take(max_suffix).take_while(|(x, y)| x == y).count();
+
+    let mut result: String = String::with_capacity(replacement.len() + inner.new_content.len());
+    if b <= prefix {
+        result.push_str(&replacement[..a]);
+        result.push_str(&inner.new_content);
+        result.push_str(&replacement[b..]);
+    } else if a >= original.len() - suffix {
+        let shift_a: usize = a + replacement.len() - original.len();
+        let shift_b: usize = b + replacement.len() - original.len();
+        result.push_str(&replacement[..shift_a]);
+        result.push_str(&inner.new_content);
+        result.push_str(&replacement[shift_b..]);
+    } else {
+        let region: &str = &original[a..b];
+        if region.is_empty() || replacement.matches(region).count() != 1 {
+            return None;
+        }
+        let pos: usize = replacement.find(region).unwrap();
+        result.push_str(&replacement[..pos]);
+        result.push_str(&inner.new_content);
+        result.push_str(&replacement[pos + region.len()..]);
+    }
+    Some(result)
+}
+
+/// A region replaced during the execution of changes. Positions in the version the change
+/// was executed on.
+#[derive(Debug, Clone)]
+struct Edit {
+    start: usize,
+    end: usize,
+    /// The length of the new content.
+    new_len: usize
+}
+
+/// Describes how an executed batch of changes moved things around.
+#[derive(Debug, Clone)]
+struct ExecutedBatch {
+    /// The version the changes were executed on.
+    from_version: usize,
+    /// The version the execution produced.
+    to_version: usize,
+    /// The changed regions, sorted by position.
+    edits: Vec<Edit>,
+    /// The replaced and replacing texts of each edit, for undoing and redoing. Dropped
+    /// once the batch falls outside the history limit.
+    texts: Option<Vec<(String, String)>>
+}
+
+impl ExecutedBatch {
+    /// Executes the batch again on the content it was originally executed on.
+    fn redo(&self, content: &str, texts: &[(String, String)]) -> String {
+        let mut result: String = String::with_capacity(content.len());
+        let mut pos: usize = 0;
+        for (edit, (_, replacement)) in self.edits.iter().zip(texts) {
+            result.push_str(&content[pos..edit.start]);
+            result.push_str(replacement);
+            pos = edit.end;
+        }
+        result.push_str(&content[pos..]);
+        result
+    }
+
+    /// Reverts the batch on the content it produced.
+    fn undo(&self, content: &str, texts: &[(String, String)]) -> String {
+        let mut result: String = String::with_capacity(content.len());
+        let mut pos: usize = 0;
+        let mut shift: isize = 0;
+        for (edit, (replaced, replacement)) in self.edits.iter().zip(texts) {
+            let start: usize = (edit.start as isize + shift) as usize;
+            result.push_str(&content[pos..start]);
+            result.push_str(replaced);
+            pos = start + replacement.len();
+            shift = shift + replacement.len() as isize - replaced.len() as isize;
+        }
+        result.push_str(&content[pos..]);
+        result
+    }
+}
+
+/// What a transaction needs to restore when rolled back.
+#[derive(Debug, Clone)]
+struct Transaction {
+    content: String,
+    version_num: usize,
+    changes: Vec<Change>,
+    history_len: usize
+}
+
+/// Registered changes applied to a copy of the content and found valid, not yet committed.
+pub(crate) struct PreparedBatch {
+    content: String,
+    edits: Vec<Edit>,
+    texts: Vec<(String, String)>,
+    questions: Vec<index::IndexedQuestion>
+}
+
+/// The byte offset of a position reported by roxmltree, rows and columns start from 1.
+fn byte_offset(text: &str, pos: roxmltree::TextPos) -> usize {
+    let line_start: usize = text.split_inclusive('\n').take(pos.row as usize - 1).map(|l| l.len()).sum();
+    let column: usize = text[line_start..].chars().take(pos.col as usize - 1).map(|c| c.len_utf8()).sum();
+    line_start + column
+}
+
+/// Applies non overlapping changes to the content, in one pass into a buffer of the
+/// right size.
+fn apply_changes(content: &str, changes: &[Change]) -> String {
+    // In document order. Insertions to the same position in registration order, and an
+    // insertion to the start of a replaced region before it, the sort is stable.
+    let mut ordered: Vec<&Change> = changes.iter().collect();
+    ordered.sort_by_key(|c| (c.position.start, c.position.end));
+    let mut size: usize = content.len();
+    for c in &ordered {
+        size = size + c.new_content.len() - (c.position.end - c.position.start);
+    }
+    let mut result: String = String::with_capacity(size);
+    let mut pos: usize = 0;
+    for c in ordered {
+        result.push_str(&content[pos..c.position.start]);
+        result.push_str(&c.new_content);
+        pos = c.position.end;
+    }
+    result.push_str(&content[pos..]);
+    result
+}
+
+/// The options we use for every parse of the document.
+fn parse_options() -> roxmltree::ParsingOptions {
+    roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..roxmltree::ParsingOptions::default()
+    }
+}
+
+/// Finds the `<question>`-element with the given index, category-questions and typeless ones
+/// are not counted.
+fn find_question_node<'a, 'input>(doc: &'a roxmltree::Document<'input>, qnum: usize) -> Result<roxmltree::Node<'a, 'input>, Error> {
+    let mut qn: usize = 0;
+    for node in doc.descendants() {
+        if node.is_element() && node.tag_name().name() == "question" {
+            match node.attribute("type") {
+                Some(qtype) => {
+                    if qtype == "category" {
+                        // We ignore these.
+                        continue;
+                    }
+                    if qn == qnum {
+                        return Ok(node);
+                    }
+                    qn += 1;
+                },
+                None => {
+                    // Ignore here.
+                }
+            }
+        }
+    }
+    Err(Error::QuestionIndexOutOfRange { index: qnum, count: qn })
+}
+
+/// What to do when registering a change that overlaps one already in the buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictPolicy {
+    /// Refuse the new change and report the conflict.
+    Reject,
+    /// The new change replaces any registered changes it overlaps.
+    LastWins,
+    /// A change identical to a registered one, same position and same new content, is 
+    /// simply dropped. Other overlaps are refused like with `Reject`.
+    MergeIdentical,
+    /// Like `MergeIdentical` but a change fully inside another change is composed into 
+    /// the outer one. The inner change is applied to the outer replacement at the spot where
+    /// the region it targets is in the outer replacement, if the outer replacement no longer 
+    /// contains that region the change is refused.
+    ComposeNested
+}
+
+/// Describes a collision between two changes.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ChangeConflict {
+    /// The change that was already in the buffer.
+    pub existing: Change,
+    /// The change that was being registered.
+    pub incoming: Change
+}
+
 /// The parser object, holding the current in-memory version of the document and keeping track of changes that are to be made to it.
 pub struct QParser {
     /// Current text content.
     content: String,
-    /// Version number of the document currently held in memory, any content refs pointing to different versions are invalid and cannot be used to target changes.
+    /// The content as it was when loaded, for comparisons.
+    original: String,
+    /// Version number of the document currently held in memory, any content refs pointing to earlier versions need to be rebased before they can be used to target changes.
     version_num: usize,
     /// Changes currently waiting for execution. We collect multiple of them and execute them at the same time so that everyone can keep using the original references for positioning, before actually executing and those lose meaning.
-    changes: Vec<Change>
+    /// Kept in document order, changes to the same position in registration order, see `buffer_position()`.
+    changes: Vec<Change>,
+    /// The regions touched by the executed batches of changes, in execution order. For rebasing references.
+    history: Vec<ExecutedBatch>,
+    /// Batches that have been undone, the latest last.
+    undone: Vec<ExecutedBatch>,
+    /// How many of the latest batches can be undone.
+    history_limit: usize,
+    /// The highest version number ever given out. Versions rolled back are never reused, so that
+    /// references to them are recognised as stale.
+    latest_version: usize,
+    /// The state to return to if the open transaction gets rolled back.
+    transaction: Option<Transaction>,
+    /// Where the questions are in some version, hopefully the current one.
+    index: Option<index::QuestionIndex>,
+    /// The encoding the document gets written in.
+    encoding: Encoding,
+    /// Whether the document started with a byte order mark.
+    bom: bool
 }
 impl QParser {
     /// Simply initialise a parser from the contents of a file.
-    pub fn load_xml_file(file_name: String) -> Result<QParser, String> {
-        let content = std::fs::read_to_string(file_name.clone()).expect("Problem reading the XML file.");
+    ///
+    /// See `try_load_xml_file()` for a version with more detailed errors.
+    pub fn load_xml_file(file_name: impl AsRef<std::path::Path>) -> Result<QParser, String> {
+        QParser::try_load_xml_file(file_name).map_err(|e| match e {
+            Error::XmlParse(_) => String::from("Errors parsing the original document."),
+            other => other.to_string()
+        })
+    }
 
-        // Check if it parses.
-        let opt = roxmltree::ParsingOptions {
-            allow_dtd: true,
-            ..roxmltree::ParsingOptions::default()
-        };
-        match roxmltree::Document::parse_with_options(&content, opt) {
-            Ok(_doc) => {
-                Ok(QParser {
-                    content: content,
-                    version_num: 0,
-                    changes: Vec::new()
-                })
-            },
-            Err(_e) => {
-                Err(String::from("Errors parsing the original document."))
-            }
-        }
+    /// Initialise a parser from the contents of a file, failing to read the file is an error
+    /// not a panic.
+    pub fn try_load_xml_file(file_name: impl AsRef<std::path::Path>) -> Result<QParser, Error> {
+        let bytes = std::fs::read(file_name)?;
+        QParser::from_bytes(bytes)
+    }
+
+    /// Reads the whole document from any source, e.g. stdin or an entry of a zip-file.
+    /// Encodings as with `from_bytes()`.
+    pub fn from_reader(mut reader: impl std::io::Read) -> Result<QParser, Error> {
+        let mut bytes: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        QParser::from_bytes(bytes)
+    }
+
+    /// Decodes a document in the encoding its XML-declaration names, UTF-8, ISO-8859-1 and
+    /// Windows-1252 are supported. A UTF-8 byte order mark is recognised as well. The
+    /// content is handled as UTF-8 and all positions are positions in that, but when
+    /// written the document gets encoded as it was.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<QParser, Error> {
+        let (content, encoding, bom) = encoding::decode(bytes)?;
+        let mut parser: QParser = QParser::try_from_string(content)?;
+        parser.encoding = encoding;
+        parser.bom = bom;
+        Ok(parser)
     }
 
     /// Parse a String that has appeared from somewhere.
+    ///
+    /// See `try_from_string()` for a version with more detailed errors.
     pub fn from_string(content: String) -> Result<QParser, String> {
+        QParser::try_from_string(content).map_err(|_e| String::from("Errors parsing the original document."))
+    }
+
+    /// Parse a String that has appeared from somewhere, should it not be valid XML the error
+    /// will tell where the problem is. The String gets written back as UTF-8 whatever it 
+    /// declares, see `from_bytes()` for other encodings.
+    pub fn try_from_string(content: String) -> Result<QParser, Error> {
         // Check if it parses.
-        let opt = roxmltree::ParsingOptions {
-            allow_dtd: true,
-            ..roxmltree::ParsingOptions::default()
-        };
-        match roxmltree::Document::parse_with_options(&content, opt) {
-            Ok(_doc) => {
-                Ok(QParser {
-                    content: content,
-                    version_num: 0,
-                    changes: Vec::new()
-                })
-            },
-            Err(_e) => {
-                Err(String::from("Errors parsing the original document."))
-            }
-        }
+        roxmltree::Document::parse_with_options(&content, parse_options())?;
+        Ok(QParser {
+            original: content.clone(),
+            content,
+            version_num: 0,
+            changes: Vec::new(),
+            history: Vec::new(),
+            undone: Vec::new(),
+            history_limit: 100,
+            latest_version: 0,
+            transaction: None,
+            index: None,
+            encoding: Encoding::Utf8,
+            bom: false
+        })
     }
 
     /// Save the current version to a file. Note that will flush the change-buffer before doing so.
@@ -428,30 +956,50 @@ impl QParser {
     /// Should the currently held version not be valid XML this will not save to a file. You can
     /// still read the current content with `get_current_content()` and use other means to write 
     /// it anywhere you wish.
-    pub fn save_to_file(&mut self, file_name: String) -> Result<(),String> {
-        self.execute_changes();
-        // We can fail fro many reasons, IO-reasons will panic, but
-        // our own insistence on valid XML will give errors.
-        let opt = roxmltree::ParsingOptions {
-            allow_dtd: true,
-            ..roxmltree::ParsingOptions::default()
-        };
-        match roxmltree::Document::parse_with_options(&self.content, opt) {
-            Ok(_doc) => {
-                match std::fs::write(file_name.clone(), self.content.clone()) {
-                    Ok(_) => {
-                        Ok(())
-                    },
-                    Err(e) => {
-                        println!("{:?}", e);
-                        Err(String::from("Failure writing file."))
-                    }
-                }
-            },
-            Err(_e) => {
-                Err(String::from("Will not write to a file due to content being broken."))
-            }
+    pub fn save_to_file(&mut self, file_name: impl AsRef<std::path::Path>) -> Result<(),String> {
+        self.try_save_to_file(file_name).map_err(|e| match e {
+            Error::XmlParse(_) | Error::BrokenChanges { .. } => String::from("Will not write to a file due to content being broken."),
+            Error::Io(_) => String::from("Failure writing file."),
+            other => other.to_string()
+        })
+    }
+
+    /// Save the current version to a file, like `save_to_file()` but with the reason for 
+    /// any failure. The file gets written atomically, see `try_save_to_file_with_options()`
+    /// for leaving files that already have the same content alone.
+    pub fn try_save_to_file(&mut self, file_name: impl AsRef<std::path::Path>) -> Result<(), Error> {
+        self.try_save_to_file_with_options(file_name, SaveOptions::default())?;
+        Ok(())
+    }
+
+    /// Writes the current version to any destination, e.g. stdout or a buffer in memory.
+    /// Like with saving to a file the change-buffer gets flushed and nothing gets written
+    /// if the content is not valid XML. If writing fails the changes stay registered.
+    pub fn write_to(&mut self, mut writer: impl std::io::Write) -> Result<(), Error> {
+        let (bytes, batch) = self.prepare_for_writing()?;
+        writer.write_all(&bytes)?;
+        writer.flush()?;
+        if let Some(batch) = batch {
+            self.commit_prepared(batch);
         }
+        Ok(())
+    }
+
+    /// The bytes to write, with any pending changes applied, and those changes to commit
+    /// once the writing has succeeded. Errors if the result is something we are not
+    /// willing to write out, in which case nothing has changed.
+    pub(crate) fn prepare_for_writing(&mut self) -> Result<(Vec<u8>, Option<PreparedBatch>), Error> {
+        let batch: Option<PreparedBatch> = self.prepare_batch()?;
+        let bytes: Vec<u8> = match &batch {
+            Some(b) => encoding::encode(&b.content, self.encoding, self.bom)?.into_owned(),
+            None => {
+                // Our own insistence on valid XML will give errors. Unless this version has
+                // already been parsed for the index.
+                self.ensure_index()?;
+                encoding::encode(&self.content, self.encoding, self.bom)?.into_owned()
+            }
+        };
+        Ok((bytes, batch))
     }
 
     /// Mainly for tests and curious minds.
@@ -459,41 +1007,81 @@ impl QParser {
         self.content.clone()
     }
 
+    /// The current version, without copying it.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// The encoding the document was read in and will be written in.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// The style most line-breaks in the document use, `\n` if there are none.
+    pub fn line_ending(&self) -> LineEnding {
+        LineEnding::detect(&self.content).unwrap_or(LineEnding::Lf)
+    }
+
+    /// Whether the document started with a byte order mark, it will be written with one as well.
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    /// Renders the registered but not yet executed changes as a unified diff against
+    /// the current version. For showing what would happen before doing it.
+    pub fn pending_changes_diff(&self, context_lines: usize) -> String {
+        if self.changes.is_empty() {
+            return String::new();
+        }
+        let pending: String = apply_changes(&self.content, &self.changes);
+        diff::unified_diff(&self.content, &pending, "current", "pending", context_lines)
+    }
+
+    /// A unified diff between the content as it was when loaded and the current version.
+    /// Registered changes that have not been executed are not included.
+    pub fn diff_from_original(&self, context_lines: usize) -> String {
+        diff::unified_diff(&self.original, &self.content, "original", "current", context_lines)
+    }
+
     /// Provides a list of questions present in the document. Only gives their types and positions
     /// not names or any other details. Mainly used to identify the indices one wants to act on by type.
+    /// Category-elements are not questions, see `find_categories()`, and `<question>`-elements
+    /// without a type are skipped.
+    ///
+    /// Panics if the document has been broken, see `try_find_questions()`.
     pub fn find_questions(&mut self) -> Vec<Question> {
-        self.execute_changes();
+        match self.try_find_questions() {
+            Ok(questions) => questions,
+            Err(e) => panic!("Error: {}", e)
+        }
+    }
+
+    /// Provides a list of questions present in the document, or an error if the document
+    /// has been broken or a question is missing its name.
+    pub fn try_find_questions(&mut self) -> Result<Vec<Question>, Error> {
+        self.try_execute_changes()?;
 
         let mut result: Vec<Question> = Vec::new();
 
-        let opt = roxmltree::ParsingOptions {
-            allow_dtd: true,
-            ..roxmltree::ParsingOptions::default()
-        };
-        let doc = match roxmltree::Document::parse_with_options(&self.content, opt) {
-            Ok(doc) => doc,
-            Err(e) => {
-                panic!("Error: {}.", e);
-            }
-        };
+        let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
 
         let mut qn: usize = 0;
+        let mut category: Option<usize> = None;
         for node in doc.descendants() {
             if node.is_element() && node.tag_name().name() == "question" {
                 match node.attribute("type") {
                     Some(qtype) => {
                         if qtype == "category" {
-                            // We ignore these.
+                            // Not a question, but the questions after this belong to it.
+                            category = Some(category.map_or(0, |c| c + 1));
                             continue;
                         }
 
                         let mut name: Option<ContentRef> = None;
                         for n in node.children() {
                             if n.is_element() && n.tag_name().name() == "name" {
-                                let text = &self._get_elements(n, vec!["text".to_string()])[0];
-                                if let ContentType::Element(_,_,items) = text {
-                                    let econtent = &items[0];
-                                    if let ContentType::ElementContent(c) = econtent {
+                                if let Some(ContentType::Element(_,_,items)) = self._get_elements(n, vec!["text".to_string()], 0)?.first() {
+                                    if let Some(ContentType::ElementContent(c)) = items.first() {
                                         name = Some(c.clone());
                                     }
                                 }
@@ -502,235 +1090,620 @@ impl QParser {
                         }
 
                         let wholetag: String = self.content[node.range().start..node.range().end].to_string();
-                        result.push(Question { qtype: qtype.to_string(), index: qn, name: name.expect("Questions should have names"), whole_element: ContentRef {
+                        result.push(Question { qtype: qtype.to_string(), index: qn, name: name.ok_or(Error::MissingElement("name".to_string()))?, whole_element: ContentRef {
                                 content: wholetag,
                                 start: node.range().start,
                                 end: node.range().end,
-                                version_num: self.version_num
-                            }
+                                version_num: self.version_num,
+                                insertion: false
+                            }, category,
+                            idnumber: self._child_content(node, &["idnumber"]),
+                            comment_id: self.preceding_question_comment(node.range().start).and_then(|c| lookup::comment_id(&self.content[c..]))
                         });
-                        qn = qn + 1;
+                        qn += 1;
                     },
                     None => {
-                        println!("Typeless question-elements are being ignored.");
+                        // Typeless question-elements are ignored.
                     }
                 }
             }
         }
-        return result;
+        // As we have parsed the whole thing.
+        self.index = Some(index::QuestionIndex {
+            version_num: self.version_num,
+            questions: index::index_questions(&doc, &self.content)
+        });
+        Ok(result)
     }
 
     /// Executes registered changes. Basically, handles them in order.
     /// DOES not write them out to any file only keeps them in memory.
+    ///
+    /// Runs as a transaction, if the result would not be valid XML nothing is executed
+    /// and the changes stay in the buffer, where the next search or save reports them.
+    /// Use `try_execute_changes()` to get the error naming the offending changes.
     pub fn execute_changes(&mut self) {
-        // Then do things, a single change happens often enough to be handled seaprately.
-        match self.changes.len() {
-            0 => {
-                // Nothing to do.
-                return;    
-            },
-            1 => {
-                let c: Change = self.changes.pop().unwrap();
-                let mut new_content: String = self.content[..c.position.start].to_string();
-                new_content.push_str(&c.new_content);
-                let end: String = self.content[c.position.end..].to_string();
-                new_content.push_str(&end);
-                self.content = new_content;
-                self.changes.clear();
-                self.version_num = self.version_num + 1;
-            },
+        // The error is not lost, the changes causing it are still registered.
+        let _ = self.try_execute_changes();
+    }
+
+    /// Executes registered changes as a transaction. The result is parsed and if it is
+    /// not valid XML the document stays as it was, the changes stay in the buffer and
+    /// the error names the changes that seem to have broken it. Searches and saving 
+    /// through the `try_`-functions execute changes this way.
+    pub fn try_execute_changes(&mut self) -> Result<(), Error> {
+        if let Some(batch) = self.prepare_batch()? {
+            self.commit_prepared(batch);
+        }
+        Ok(())
+    }
+
+    /// Applies the registered changes to a copy of the content and checks that the result
+    /// is valid XML, without touching the document. None if there is nothing to execute.
+    pub(crate) fn prepare_batch(&self) -> Result<Option<PreparedBatch>, Error> {
+        if self.changes.is_empty() {
+            return Ok(None);
+        }
+        let new_content: String = apply_changes(&self.content, &self.changes);
+        let (edits, texts) = self.batch_edits();
+
+        // If the changes stay inside questions it is enough to check those questions.
+        let mut rebased: Option<(Vec<index::IndexedQuestion>, Vec<usize>)> = match &self.index {
+            Some(index) if index.version_num == self.version_num => index.rebase(&edits, &texts),
+            _ => None
+        };
+        if let Some((questions, touched)) = &rebased {
+            let valid: bool = touched.iter().all(|qn| {
+                let q: &index::IndexedQuestion = &questions[*qn];
+                roxmltree::Document::parse_with_options(&new_content[q.start..q.end], parse_options()).is_ok()
+            });
+            if !valid {
+                rebased = None;
+            }
+        }
+        let questions: Vec<index::IndexedQuestion> = match rebased {
+            Some((questions, _)) => questions,
+            None => match roxmltree::Document::parse_with_options(&new_content, parse_options()) {
+                Ok(doc) => index::index_questions(&doc, &new_content),
+                Err(e) => {
+                    let offending: Vec<Change> = self.offending_changes(&new_content, &e);
+                    return Err(Error::BrokenChanges { error: e, offending });
+                }
+            }
+        };
+        Ok(Some(PreparedBatch {
+            content: new_content,
+            edits,
+            texts,
+            questions
+        }))
+    }
+
+    /// Commits a batch from `prepare_batch()`, nothing may have been registered in between.
+    pub(crate) fn commit_prepared(&mut self, batch: PreparedBatch) {
+        self.commit_batch(batch.content, batch.edits, batch.texts);
+        self.index = Some(index::QuestionIndex {
+            version_num: self.version_num,
+            questions: batch.questions
+        });
+    }
+
+    /// The regions the buffered changes replace, sorted by position, and the replaced and
+    /// replacing texts.
+    fn batch_edits(&self) -> (Vec<Edit>, Vec<(String, String)>) {
+        let mut ordered: Vec<&Change> = self.changes.iter().collect();
+        ordered.sort_by_key(|c| (c.position.start, c.position.end));
+        let edits: Vec<Edit> = ordered.iter().map(|c| Edit {
+            start: c.position.start,
+            end: c.position.end,
+            new_len: c.new_content.len()
+        }).collect();
+        let texts: Vec<(String, String)> = ordered.iter().map(|c| {
+            (self.content[c.position.start..c.position.end].to_string(), c.new_content.clone())
+        }).collect();
+        (edits, texts)
+    }
+
+    /// Replaces the content with the result of executing the buffered changes, and
+    /// moves to a new version. Records what changed so that older references can be 
+    /// rebased and the batch undone.
+    fn commit_batch(&mut self, new_content: String, edits: Vec<Edit>, texts: Vec<(String, String)>) {
+        self.latest_version += 1;
+        self.history.push(ExecutedBatch {
+            from_version: self.version_num,
+            to_version: self.latest_version,
+            edits,
+            texts: Some(texts)
+        });
+        self.undone.clear();
+        self.trim_history();
+
+        self.content = new_content;
+        self.changes.clear();
+        self.version_num = self.latest_version;
+    }
+
+    /// Drops the texts of batches that fall outside the history limit, the positions
+    /// are still needed for rebasing.
+    fn trim_history(&mut self) {
+        let mut undoable: usize = 0;
+        for batch in self.history.iter_mut().rev() {
+            if batch.texts.is_some() {
+                undoable += 1;
+                if undoable > self.history_limit {
+                    batch.texts = None;
+                }
+            }
+        }
+    }
+
+    /// Sets how many of the latest executed batches can be undone, 100 by default.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        self.trim_history();
+        // The next one to redo is at the end.
+        let excess: usize = self.undone.len().saturating_sub(limit);
+        self.undone.drain(..excess);
+    }
+
+    /// Reverts the latest executed batch of changes, returning to the version it was
+    /// executed on. References from that version are valid again, those from the undone
+    /// version become stale until it is redone. Returns false if there was nothing to undo.
+    ///
+    /// Registered changes need to be executed or discarded first, and one cannot undo past
+    /// the start of an open transaction.
+    pub fn undo(&mut self) -> Result<bool, Error> {
+        if !self.changes.is_empty() {
+            return Err(Error::PendingChanges);
+        }
+        if let Some(transaction) = &self.transaction {
+            if self.history.len() <= transaction.history_len {
+                return Err(Error::TransactionState("cannot undo past the start of the transaction".to_string()));
+            }
+        }
+        let batch: ExecutedBatch = match self.history.last() {
+            Some(b) if b.texts.is_some() && b.to_version == self.version_num => self.history.pop().unwrap(),
             _ => {
-                self.changes.sort_by(|a,b| b.position.start.cmp(&a.position.start));
-                for c in &self.changes {
-                    let mut new_content: String = self.content[..c.position.start].to_string();
-                    new_content.push_str(&c.new_content);
-                    let end: String = self.content[c.position.end..].to_string();
-                    new_content.push_str(&end);
-                    self.content = new_content;
+                return Ok(false);
+            }
+        };
+        self.content = batch.undo(&self.content, batch.texts.as_ref().unwrap());
+        self.version_num = batch.from_version;
+        self.undone.push(batch);
+        Ok(true)
+    }
+
+    /// Executes the latest undone batch again, returning to the version it produced.
+    /// Returns false if there was nothing to redo, executing new changes after an undo
+    /// means that there is nothing to redo. Neither is there if the batch was executed on
+    /// some other version than the current one.
+    pub fn redo(&mut self) -> Result<bool, Error> {
+        if !self.changes.is_empty() {
+            return Err(Error::PendingChanges);
+        }
+        let batch: ExecutedBatch = match self.undone.last() {
+            Some(b) if b.texts.is_some() && b.from_version == self.version_num => self.undone.pop().unwrap(),
+            _ => {
+                return Ok(false);
+            }
+        };
+        self.content = batch.redo(&self.content, batch.texts.as_ref().unwrap());
+        self.version_num = batch.to_version;
+        self.history.push(batch);
+        Ok(true)
+    }
+
+    /// Which of the registered changes broke the document. Those whose new content is
+    /// where the parser found the problem, or if none of them are then those that break
+    /// the document even when executed alone. Missing end-tags for example get reported
+    /// at the end of the document.
+    fn offending_changes(&self, new_content: &str, error: &roxmltree::Error) -> Vec<Change> {
+        let position: usize = byte_offset(new_content, error.pos());
+        let mut ordered: Vec<&Change> = self.changes.iter().collect();
+        ordered.sort_by_key(|c| (c.position.start, c.position.end));
+        let mut result: Vec<Change> = Vec::new();
+        let mut shift: isize = 0;
+        for c in ordered {
+            let start: usize = (c.position.start as isize + shift) as usize;
+            if start <= position && position <= start + c.new_content.len() {
+                result.push(c.clone());
+            }
+            shift = shift + c.new_content.len() as isize - (c.position.end - c.position.start) as isize;
+        }
+        if result.is_empty() {
+            for c in &self.changes {
+                let alone: String = apply_changes(&self.content, std::slice::from_ref(c));
+                if roxmltree::Document::parse_with_options(&alone, parse_options()).is_err() {
+                    result.push(c.clone());
                 }
-                self.changes.clear();
-                self.version_num = self.version_num + 1;
             }
         }
+        result
+    }
+
+    /// Empties the change buffer without executing anything, returns the changes that were
+    /// in it in document order. For recovering from changes that would break the document.
+    pub fn discard_changes(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Starts a transaction spanning multiple batches of changes. Until it is committed
+    /// everything executed, and the changes registered before this, can be rolled back.
+    /// Transactions do not nest.
+    pub fn begin_transaction(&mut self) -> Result<(), Error> {
+        if self.transaction.is_some() {
+            return Err(Error::TransactionState("a transaction is already open".to_string()));
+        }
+        self.transaction = Some(Transaction {
+            content: self.content.clone(),
+            version_num: self.version_num,
+            changes: self.changes.clone(),
+            history_len: self.history.len()
+        });
+        Ok(())
+    }
+
+    /// Executes any registered changes and ends the transaction. If the document is
+    /// broken, or those changes would break it, the transaction stays open so that one
+    /// can still roll back.
+    pub fn commit_transaction(&mut self) -> Result<(), Error> {
+        if self.transaction.is_none() {
+            return Err(Error::TransactionState("no transaction to commit".to_string()));
+        }
+        self.try_execute_changes()?;
+        self.transaction = None;
+        Ok(())
+    }
+
+    /// Returns the document, its version and the change buffer to the state they were in
+    /// when the transaction began. References from searches done during the transaction
+    /// become stale, older ones stay usable. Nothing can be redone after this.
+    pub fn rollback_transaction(&mut self) -> Result<(), Error> {
+        let transaction: Transaction = match self.transaction.take() {
+            Some(t) => t,
+            None => {
+                return Err(Error::TransactionState("no transaction to roll back".to_string()));
+            }
+        };
+        self.content = transaction.content;
+        self.version_num = transaction.version_num;
+        self.changes = transaction.changes;
+        self.history.truncate(transaction.history_len);
+        self.undone.clear();
+        Ok(())
     }
 
     /// Adds a change to the change buffer, to be executed at some later moment.
     ///
     /// Note that this function will panic if given a change that overlaps any
     /// previously given non executed one. Use of this toolset needs to execute changes
-    /// in suitable batches and order so that this does not happen.
+    /// in suitable batches and order so that this does not happen. See `try_register_change()`
+    /// for a version that does not panic.
     pub fn register_change(&mut self, change: Change) {
+        if let Err(e) = self.try_register_change(change) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Adds a change to the change buffer, to be executed at some later moment.
+    ///
+    /// Returns an error, and leaves the buffer as it was, if the change targets a stale
+    /// version of the document or overlaps any previously given non executed change.
+    /// In the latter case the error describes the collision.
+    pub fn try_register_change(&mut self, change: Change) -> Result<(), Error> {
+        self.try_register_change_with_policy(change, ConflictPolicy::Reject)
+    }
+
+    /// Adds a change to the change buffer, dealing with overlapping changes as the policy 
+    /// says. References from earlier versions get rebased, and if that is not possible
+    /// that is an error.
+    pub fn try_register_change_with_policy(&mut self, mut change: Change, policy: ConflictPolicy) -> Result<(), Error> {
         if change.position.version_num != self.version_num {
-            panic!("Use of a content-reference to a stale search result detected.");
+            change.position = self.rebase(&change.position)?;
         }
-        if self.changes.is_empty() {
-            self.changes.push(change);
-        } else {
-            let mut good = true;
-            for existing in &self.changes {
-                // Is this inside it?
-                if change.position.start < existing.position.end && change.position.start >= existing.position.start {
-                    good = false;
-                    break;
-                } else if change.position.end <= existing.position.end && change.position.end > existing.position.start {
-                    good = false;
-                    break;
+        let neighbours: std::ops::Range<usize> = self.neighbours(&change.position);
+        if policy != ConflictPolicy::Reject && policy != ConflictPolicy::LastWins && self.changes[neighbours.clone()].contains(&change) {
+            // Already there.
+            return Ok(());
+        }
+        let conflicting: Vec<usize> = neighbours.filter(|i| overlaps(&change.position, &self.changes[*i].position)).collect();
+        if !conflicting.is_empty() && policy == ConflictPolicy::ComposeNested {
+            if conflicting.len() == 1 && contains(&self.changes[conflicting[0]].position, &change.position) {
+                // Goes inside an existing one.
+                let outer = &self.changes[conflicting[0]];
+                match compose(&self.content, outer, &change) {
+                    Some(composed) => {
+                        self.changes[conflicting[0]].new_content = composed;
+                        return Ok(());
+                    },
+                    None => {
+                        return Err(Error::UncomposableChange(Box::new(ChangeConflict {
+                            existing: outer.clone(),
+                            incoming: change
+                        })));
+                    }
                 }
-                // Is it inside this?
-                if existing.position.start < change.position.end && existing.position.start >= change.position.start {
-                    good = false;
-                    break;
-                } else if existing.position.end <= change.position.end && existing.position.end > change.position.start {
-                    good = false;
-                    break;
+            }
+            if conflicting.iter().all(|i| contains(&change.position, &self.changes[*i].position)) {
+                // Existing ones go inside this one.
+                let mut outer = change;
+                for i in &conflicting {
+                    let inner = &self.changes[*i];
+                    match compose(&self.content, &outer, inner) {
+                        Some(composed) => {
+                            outer.new_content = composed;
+                        },
+                        None => {
+                            return Err(Error::UncomposableChange(Box::new(ChangeConflict {
+                                existing: inner.clone(),
+                                incoming: outer
+                            })));
+                        }
+                    }
                 }
+                for i in conflicting.into_iter().rev() {
+                    self.changes.remove(i);
+                }
+                let at: usize = self.buffer_position(&outer.position);
+                self.changes.insert(at, outer);
+                return Ok(());
+            }
+        }
+        if !conflicting.is_empty() {
+            if policy != ConflictPolicy::LastWins {
+                return Err(Error::OverlappingChange(Box::new(ChangeConflict {
+                    existing: self.changes[conflicting[0]].clone(),
+                    incoming: change
+                })));
             }
-            if !good {
-                panic!("Overlap of uncommitted changes, cannot continue.");
+            // Drop the ones this replaces, from the end so that indices stay valid.
+            for i in conflicting.into_iter().rev() {
+                self.changes.remove(i);
             }
-            self.changes.push(change);
         }
+        let at: usize = self.buffer_position(&change.position);
+        self.changes.insert(at, change);
+        Ok(())
     }
 
+    /// Registers the changes all or nothing, if one of them cannot be registered the buffer
+    /// is left as it was.
+    pub(crate) fn try_register_changes(&mut self, changes: Vec<(Change, ConflictPolicy)>) -> Result<(), Error> {
+        let buffer: Vec<Change> = self.changes.clone();
+        for (change, policy) in changes {
+            if let Err(e) = self.try_register_change_with_policy(change, policy) {
+                self.changes = buffer;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
 
-    /// Searches of various elements from within a singular question.
-    ///
-    /// You may name as many tag-names you want, and the tool tries to 
-    /// return sensible ContentType objects describing things.
-    pub fn get_elements(&mut self, qnum: usize, tagnames: Vec<String>) -> Vec<ContentType> {
-        self.execute_changes();
+    /// Where in the buffer a change to the position goes, after the changes before it and
+    /// the changes to the same range registered before it.
+    fn buffer_position(&self, position: &ContentRef) -> usize {
+        self.changes.partition_point(|c| (c.position.start, c.position.end) <= (position.start, position.end))
+    }
+
+    /// The registered changes that may overlap the position. Those do not overlap each
+    /// other, so in document order their ends are in order as well and both ends of the
+    /// range can be searched for.
+    fn neighbours(&self, position: &ContentRef) -> std::ops::Range<usize> {
+        let first: usize = self.changes.partition_point(|c| c.position.end < position.start);
+        let last: usize = first + self.changes[first..].partition_point(|c| c.position.start <= position.end);
+        first..last
+    }
 
-        let opt = roxmltree::ParsingOptions {
-            allow_dtd: true,
-            ..roxmltree::ParsingOptions::default()
+
+    /// Maps a reference from a search targeting an earlier version of the document to
+    /// the current version. This is possible as long as the region the reference targets
+    /// has not been touched by the changes executed since, changes elsewhere only move it.
+    ///
+    /// Note that registering changes does this automatically.
+    pub fn rebase(&self, reference: &ContentRef) -> Result<ContentRef, Error> {
+        if reference.version_num == self.version_num {
+            return Ok(reference.clone());
+        }
+        let stale = Error::StaleReference {
+            reference_version: reference.version_num,
+            current_version: self.version_num
         };
-        let doc = match roxmltree::Document::parse_with_options(&self.content, opt) {
-            Ok(doc) => doc,
-            Err(e) => {
-                panic!("Error: {}.", e);
+        let first: usize = match self.history.iter().position(|b| b.from_version == reference.version_num) {
+            Some(i) => i,
+            None => {
+                return Err(stale);
             }
         };
 
-        let mut qn: usize = 0;
-        for node in doc.descendants() {
-            if node.is_element() && node.tag_name().name() == "question" {
-                match node.attribute("type") {
-                    Some(qtype) => {
-                        if qtype == "category" {
-                            // We ignore these.
-                            continue;
-                        }
-                        if qn == qnum {
-                            return self._get_elements(node, tagnames)
-                        }
-                        qn = qn + 1;
-                    },
-                    None => {
-                        // Ignore here.
-                    }
+        let mut start: usize = reference.start;
+        let mut end: usize = reference.end;
+        for batch in &self.history[first..] {
+            let mut new_start: usize = start;
+            let mut new_end: usize = end;
+            for edit in &batch.edits {
+                if edit.end <= start {
+                    // Before, moves the whole reference.
+                    new_start = new_start + edit.new_len - (edit.end - edit.start);
+                    new_end = new_end + edit.new_len - (edit.end - edit.start);
+                } else if edit.start >= end {
+                    // After, and the edits are sorted so nothing more to see.
+                    break;
+                } else {
+                    return Err(stale);
                 }
             }
+            start = new_start;
+            end = new_end;
+        }
+
+        Ok(ContentRef {
+            content: reference.content.clone(),
+            start,
+            end,
+            version_num: self.version_num,
+            insertion: reference.insertion
+        })
+    }
+
+    /// A reference to a range of the current version.
+    pub(crate) fn content_ref(&self, start: usize, end: usize) -> ContentRef {
+        ContentRef {
+            content: self.content[start..end].to_string(),
+            start,
+            end,
+            version_num: self.version_num,
+            insertion: false
+        }
+    }
+
+    /// A point in the current version where a structural operation inserts something.
+    pub(crate) fn insertion_point(&self, pos: usize) -> ContentRef {
+        ContentRef {
+            insertion: true,
+            ..self.content_ref(pos, pos)
+        }
+    }
+
+    /// Searches of various elements from within a singular question.
+    ///
+    /// You may name as many tag-names you want, and the tool tries to 
+    /// return sensible ContentType objects describing things.
+    ///
+    /// Panics if the question does not exist or an element cannot be described, see
+    /// `try_get_elements()`.
+    pub fn get_elements(&mut self, qnum: usize, tagnames: Vec<String>) -> Vec<ContentType> {
+        match self.try_get_elements(qnum, tagnames) {
+            Ok(elements) => elements,
+            Err(e) => panic!("{}", e)
         }
-        panic!("{}",format!("Only {qn} questions, but was trying to get index {qnum}."));
     }
 
-    fn _get_elements(&self, qnode: roxmltree::Node, tagnames: Vec<String>) -> Vec<ContentType> {
+    /// Searches of various elements from within a singular question, or tells why that
+    /// question could not be searched.
+    pub fn try_get_elements(&mut self, qnum: usize, tagnames: Vec<String>) -> Result<Vec<ContentType>, Error> {
+        self.with_question(qnum, |parser, node, base| parser._get_elements(node, tagnames, base))
+    }
+    fn _get_elements(&self, qnode: roxmltree::Node, tagnames: Vec<String>, base: usize) -> Result<Vec<ContentType>, Error> {
         // qnode here is a question element.
         let mut result: Vec<ContentType> = Vec::new();
 
         for node in qnode.descendants() {
             if node.is_element() && tagnames.contains(&node.tag_name().name().to_string()) {
-                // Is this element something with format and an internal text element as well as attachements?
-                let mut maybe_moodle_text_node: Option<ContentRef> = None;
-                let mut surely_moodle_text_node = false;
-                let mut parts: Vec<ContentType> = Vec::new();
-                // First attributes if any.
-                for attr in node.attributes() {
-                    // We need the position of the content inside quotes.
-                    let start = &self.content[attr.range().start..].find(|c| c == '"' || c == '\'').unwrap() + attr.range().start + 1;
-                    let quotetype: char = self.content[start-1..].chars().nth(0).unwrap();
-                    let end = &self.content[start..].find(|c| c == quotetype).unwrap() + start;
-                    let rawattr: String = self.content[start..end].to_string();
-                    let cr: ContentRef = ContentRef {
-                        content: rawattr,
-                        start: start,
-                        end: end,
-                        version_num: self.version_num
-                    };
-                    if attr.name().to_string() == "format" {
-                        maybe_moodle_text_node = Some(cr.clone());
-                    }
-                    let v = ContentType::AttributeValue (attr.name().to_string(), cr);
-                    parts.push(v);
-                }
+                result.push(self._get_element(node, base)?);
+            }
+        }
 
-                // Then the content, if any...
-                let wholetag: String = self.content[node.range().start..node.range().end].to_string();
-                if &wholetag[node.range().end-node.range().start-2..] != "/>" && node.children().count() > 0 {
-                    // So we can extract the internal bit, thus we have content.
-                    let mut children = node.children();
-                    // The first child will give us the start of the range and so on.
-                    let first = children.next().unwrap();
-                    let last = match children.last() {
-                        None => {
-                            first
-                        }, 
-                        Some(n) => {
-                            n
-                        }
-                    };
-                    // Check for that MoodleTextConstruct.
-                    if maybe_moodle_text_node != None {
-                        for n in node.children() {
-                            if n.is_element() && n.tag_name().name().to_string() == "text".to_string() {
-                                surely_moodle_text_node = true;
-                                break;
-                            }
-                        }
-                    }
+        Ok(result)
+    }
+
+    /// Describes a single element, its attributes and content. Errors if the element
+    /// looks like a Moodle text-field but its `<text>`-element has no content to point at.
+    ///
+    /// The base is where the text the node was parsed from starts in the content, searches
+    /// parse single questions from slices of it and the positions roxmltree gives are
+    /// relative to that.
+    pub(crate) fn _get_element(&self, node: roxmltree::Node, base: usize) -> Result<ContentType, Error> {
+        let range = |n: roxmltree::Node| (n.range().start + base)..(n.range().end + base);
+
+        // Is this element something with format and an internal text element as well as attachements?
+        let mut maybe_moodle_text_node: Option<ContentRef> = None;
+        let mut surely_moodle_text_node = false;
+        let mut parts: Vec<ContentType> = Vec::new();
+        // First attributes if any.
+        for attr in node.attributes() {
+            // We need the position of the content inside quotes.
+            let attr_start: usize = attr.range().start + base;
+            let unexpected = || Error::UnexpectedElement(node.tag_name().name().to_string());
+            let start = self.content[attr_start..].find(['"', '\'']).ok_or_else(unexpected)? + attr_start + 1;
+            let quotetype: char = self.content[start-1..].chars().next().ok_or_else(unexpected)?;
+            let end = self.content[start..].find(quotetype).ok_or_else(unexpected)? + start;
+            let rawattr: String = self.content[start..end].to_string();
+            let cr: ContentRef = ContentRef {
+                content: rawattr,
+                start,
+                end,
+                version_num: self.version_num,
+                insertion: false
+            };
+            if attr.name() == "format" {
+                maybe_moodle_text_node = Some(cr.clone());
+            }
+            let v = ContentType::AttributeValue (attr.name().to_string(), cr);
+            parts.push(v);
+        }
 
-                    let inner: String = self.content[first.range().start..last.range().end].to_string();
-                    let v = ContentType::ElementContent (ContentRef {
-                        content: inner.to_string().clone(),
-                        start: first.range().start,
-                        end: last.range().end,
-                        version_num: self.version_num
-                    });
-                    parts.push(v);
-                } else if &wholetag[node.range().end-node.range().start-2..] != "/>" {
-                    // Not an "empty"-tag but still empty... We need to identify the position of that "><".
-                    let pos = node.range().start + wholetag.find("><").unwrap() + 1;
-                    let v = ContentType::ElementContent (ContentRef {
-                        content: "".to_string(),
-                        start: pos,
-                        end: pos,
-                        version_num: self.version_num             
-                    });
-                    parts.push(v);
+        // Then the content, if any...
+        let wholetag: String = self.content[range(node)].to_string();
+        if &wholetag[range(node).end-range(node).start-2..] != "/>" && node.children().count() > 0 {
+            // So we can extract the internal bit, thus we have content.
+            // The first child will give us the start of the range, the end-tag the end.
+            // The last child cannot be trusted for that, text mixed with CDATA and entities
+            // is one node whose range only covers the first part of it.
+            let first = node.first_child().ok_or_else(|| Error::UnexpectedElement(node.tag_name().name().to_string()))?;
+            let inner_end: usize = range(node).start + wholetag.rfind("</").ok_or_else(|| Error::UnexpectedElement(node.tag_name().name().to_string()))?;
+            // Check for that MoodleTextConstruct.
+            if maybe_moodle_text_node.is_some() {
+                for n in node.children() {
+                    if n.is_element() && n.tag_name().name() == "text" {
+                        surely_moodle_text_node = true;
+                        break;
+                    }
                 }
-                // Certain common constructs require special handling.
-                if !surely_moodle_text_node {
-                    result.push(ContentType::Element(node.tag_name().name().to_string(), ContentRef {
-                        content: wholetag,
-                        start: node.range().start,
-                        end: node.range().end,
-                        version_num: self.version_num
-                    }, parts));
-                } else {
-                    // Recurse those inner elements
-                    let mut els: Vec<ContentType> = self._get_elements(node, vec!["file".to_string()]);
-                    // We unwrap the text-element and place it as the first element in the list of parts, for ease of access.
-                    let the_text_element: ContentType = self._get_elements(node, vec!["text".to_string()])[0].clone();
-                    if let ContentType::Element(_name, _whole, prts) = the_text_element {
-                        // Always have the text-elements content as the first in that list that might contain attachemnt files.
-                        els.insert(0, prts.last().unwrap().clone());
+            }
+
+            let inner: String = self.content[range(first).start..inner_end].to_string();
+            let v = ContentType::ElementContent (ContentRef {
+                content: inner.to_string().clone(),
+                start: range(first).start,
+                end: inner_end,
+                version_num: self.version_num,
+                insertion: false
+            });
+            parts.push(v);
+        } else if &wholetag[range(node).end-range(node).start-2..] != "/>" {
+            // Not an "empty"-tag but still empty... We need to identify the position of that "><".
+            let pos = range(node).start + wholetag.find("><").ok_or_else(|| Error::UnexpectedElement(node.tag_name().name().to_string()))? + 1;
+            let v = ContentType::ElementContent (ContentRef {
+                content: "".to_string(),
+                start: pos,
+                end: pos,
+                version_num: self.version_num,
+                insertion: false
+            });
+            parts.push(v);
+        }
+        // Certain common constructs require special handling.
+        if !surely_moodle_text_node {
+            Ok(ContentType::Element(node.tag_name().name().to_string(), ContentRef {
+                content: wholetag,
+                start: range(node).start,
+                end: range(node).end,
+                version_num: self.version_num,
+                insertion: false
+            }, parts))
+        } else {
+            // Recurse those inner elements
+            let mut els: Vec<ContentType> = self._get_elements(node, vec!["file".to_string()], base)?;
+            // We unwrap the text-element and place it as the first element in the list of parts, for ease of access.
+            let the_text_element: Option<ContentType> = self._get_elements(node, vec!["text".to_string()], base)?.into_iter().next();
+            match the_text_element {
+                Some(ContentType::Element(_name, _whole, prts)) => match prts.last() {
+                    // Always have the text-elements content as the first in that list that might contain attachemnt files.
+                    Some(content @ ContentType::ElementContent(_)) => els.insert(0, content.clone()),
+                    // An empty-tag, nowhere to place content.
+                    _ => {
+                        return Err(Error::UnexpectedElement("text".to_string()));
                     }
-                    result.push(ContentType::MoodleTextElement(node.tag_name().name().to_string(), maybe_moodle_text_node.unwrap(), els));
+                },
+                _ => {
+                    return Err(Error::MissingElement("text".to_string()));
                 }
             }
+            match maybe_moodle_text_node {
+                Some(format) => Ok(ContentType::MoodleTextElement(node.tag_name().name().to_string(), format, els)),
+                None => Err(Error::MissingElement("format".to_string()))
+            }
         }
-
-        return result;
     }
 }
 
@@ -0,0 +1,77 @@
+//! Turning references into positions people and editors understand, for reporting
+//! problems found in the content.
+
+use crate::{ContentRef, Error, QParser, parse_options};
+
+/// A position in the document, lines and columns start from 1 like in editors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextPosition {
+    /// The byte offset in the document.
+    pub offset: usize,
+    /// The line.
+    pub line: usize,
+    /// The column in characters.
+    pub column: usize,
+    /// The column in bytes.
+    pub byte_column: usize
+}
+
+/// Where a reference points to in the current version of the document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    /// The start of the reference.
+    pub start: TextPosition,
+    /// The end of the reference, exclusive.
+    pub end: TextPosition,
+    /// The index of the question the reference is in, None if it is not inside a question.
+    pub question: Option<usize>,
+    /// The name of that question, with CDATA and entities decoded.
+    pub question_name: Option<String>
+}
+
+/// The line and columns of the offset.
+fn text_position(content: &str, offset: usize) -> TextPosition {
+    let before: &str = &content[..offset];
+    let line_start: usize = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    TextPosition {
+        offset,
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+        byte_column: offset - line_start + 1
+    }
+}
+
+/// The decoded name of the question parsed from the text of the question.
+fn name_of_question(question: &str) -> Option<String> {
+    let doc = roxmltree::Document::parse_with_options(question, parse_options()).ok()?;
+    let name = doc.root_element().children().find(|n| n.is_element() && n.tag_name().name() == "name")?;
+    let text = name.children().find(|n| n.is_element() && n.tag_name().name() == "text")?;
+    Some(text.descendants().filter(|n| n.is_text()).map(|n| n.text().unwrap_or_default()).collect())
+}
+
+impl QParser {
+    /// Where the reference points to, as lines and columns, and which question it is in.
+    /// References from earlier versions get rebased. Registered changes are not executed,
+    /// the location is in the current version.
+    ///
+    /// The question is left out if the document is broken.
+    pub fn locate(&mut self, reference: &ContentRef) -> Result<Location, Error> {
+        let reference: ContentRef = self.rebase(reference)?;
+        let mut question: Option<usize> = None;
+        let mut question_name: Option<String> = None;
+        if self.ensure_index().is_ok() {
+            let questions = &self.index.as_ref().unwrap().questions;
+            // The innermost one, should there be questions inside questions.
+            if let Some(qnum) = questions.iter().rposition(|q| q.start <= reference.start && reference.end <= q.end) {
+                question = Some(qnum);
+                question_name = name_of_question(&self.content[questions[qnum].start..questions[qnum].end]);
+            }
+        }
+        Ok(Location {
+            start: text_position(&self.content, reference.start),
+            end: text_position(&self.content, reference.end),
+            question,
+            question_name
+        })
+    }
+}
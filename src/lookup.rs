@@ -0,0 +1,87 @@
+//! Finding single questions by what identifies them, instead of going through the list
+//! `find_questions()` gives.
+
+use crate::{Error, QParser, Question};
+
+/// The id in a `<!-- question: N -->`-comment at the start of the text.
+pub(crate) fn comment_id(text: &str) -> Option<u64> {
+    let rest: &str = text.strip_prefix("<!--")?.trim_start().strip_prefix("question:")?.trim_start();
+    let digits: usize = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    rest[..digits].parse::<u64>().ok()
+}
+
+/// Does the text match the pattern, `*` matches any number of characters and `?` one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let mut p: usize = 0;
+    let mut t: usize = 0;
+    // Where the last star was and where in the text it started matching.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || (pattern[p] != '*' && pattern[p] == text[t])) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the star take one more character.
+            backtrack = Some((star, matched + 1));
+            p = star + 1;
+            t = matched + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl QParser {
+    /// The only question the predicate accepts, the query describes it for the errors.
+    fn _find_question(&mut self, query: String, predicate: impl Fn(&Question) -> bool) -> Result<Question, Error> {
+        let mut matches: Vec<Question> = self.try_find_questions()?.into_iter().filter(|q| predicate(q)).collect();
+        match matches.len() {
+            0 => Err(Error::NoSuchQuestion(query)),
+            1 => Ok(matches.remove(0)),
+            _ => Err(Error::AmbiguousQuestion { query, indices: matches.iter().map(|q| q.index).collect() })
+        }
+    }
+
+    /// Finds the question with the name. The name may be a pattern with `*` matching any
+    /// number of characters and `?` matching one, it is matched against the whole name
+    /// with CDATA and entities decoded and surrounding whitespace trimmed. For names with
+    /// those characters in them see `find_question_by_exact_name()`.
+    ///
+    /// Errors if there is no such question or more than one of them.
+    pub fn find_question_by_name(&mut self, name: &str) -> Result<Question, Error> {
+        self._find_question(format!("name '{name}'"), |q| glob_match(name, q.name.decode().trim()))
+    }
+
+    /// Finds the question with exactly the name, for names that have `*` or `?` in them.
+    /// Matched like with `find_question_by_name()` but without the patterns.
+    ///
+    /// Errors if there is no such question or more than one of them.
+    pub fn find_question_by_exact_name(&mut self, name: &str) -> Result<Question, Error> {
+        self._find_question(format!("exact name '{name}'"), |q| q.name.decode().trim() == name)
+    }
+
+    /// Finds the question with the `<idnumber>`.
+    ///
+    /// Errors if there is no such question or more than one of them.
+    pub fn find_question_by_idnumber(&mut self, idnumber: &str) -> Result<Question, Error> {
+        self._find_question(format!("idnumber '{idnumber}'"), |q| match &q.idnumber {
+            Some(i) => i.decode().trim() == idnumber,
+            None => false
+        })
+    }
+
+    /// Finds the question exported with the given id, i.e. the one after the
+    /// `<!-- question: N -->`-comment with that id.
+    ///
+    /// Errors if there is no such question or more than one of them.
+    pub fn find_question_by_comment_id(&mut self, id: u64) -> Result<Question, Error> {
+        self._find_question(format!("comment id {id}"), |q| q.comment_id == Some(id))
+    }
+}
+
@@ -0,0 +1,133 @@
+//! Writing the document to a file so that an interrupted save never leaves a half
+//! written file behind. The content goes to a temporary file next to the target, which
+//! then replaces the target in one rename.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::{Error, QParser};
+
+/// How to save a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SaveOptions {
+    /// Copy the file being replaced to `<name>.bak` first.
+    pub backup: bool,
+    /// Do not touch the file at all if it already has the same content, so that its
+    /// modification time stays as it was.
+    pub skip_unchanged: bool
+}
+
+impl Default for SaveOptions {
+    /// No backups, the file is written even if it already has the same content. The
+    /// same as what `save_to_file()` does.
+    fn default() -> SaveOptions {
+        SaveOptions {
+            backup: false,
+            skip_unchanged: false
+        }
+    }
+}
+
+/// What saving did.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaveOutcome {
+    /// The file was written.
+    Written,
+    /// The file already had this content and was not touched.
+    Unchanged
+}
+
+/// Creates a new temporary file in the directory, with a name that does not collide.
+fn create_temporary(dir: &Path, file_name: &str) -> Result<(PathBuf, File), Error> {
+    let mut attempt: usize = 0;
+    loop {
+        let path: PathBuf = dir.join(format!(".{file_name}.{}.{attempt}.tmp", std::process::id()));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => {
+                return Ok((path, file));
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                attempt += 1;
+            },
+            Err(e) => {
+                return Err(Error::Io(e));
+            }
+        }
+    }
+}
+
+/// Fills the temporary file and moves it over the target, backing up the target first
+/// if asked to.
+fn replace_with(temporary: &Path, mut file: File, content: &[u8], target: &Path, existing: Option<&std::fs::Metadata>, backup: bool) -> Result<(), Error> {
+    file.write_all(content)?;
+    if let Some(metadata) = existing {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()?;
+    if existing.is_some() && backup {
+        let mut backup_name: std::ffi::OsString = target.as_os_str().to_os_string();
+        backup_name.push(".bak");
+        std::fs::copy(target, backup_name)?;
+    }
+    std::fs::rename(temporary, target)?;
+    Ok(())
+}
+
+impl QParser {
+    /// Saves the current version to a file as the options say. Like with `save_to_file()`
+    /// the change-buffer gets flushed and nothing gets written if the content is not
+    /// valid XML, or cannot be represented in the encoding of the document. A failed
+    /// save leaves the changes registered and the document as it was.
+    ///
+    /// The file is written atomically, an existing file keeps its permissions and if it
+    /// is a symbolic link the file it points to gets replaced.
+    pub fn try_save_to_file_with_options(&mut self, file_name: impl AsRef<Path>, options: SaveOptions) -> Result<SaveOutcome, Error> {
+        let (bytes, batch) = self.prepare_for_writing()?;
+        let mut target: PathBuf = file_name.as_ref().to_path_buf();
+        let existing: Option<std::fs::Metadata> = match std::fs::metadata(&target) {
+            Ok(metadata) => {
+                target = std::fs::canonicalize(&target)?;
+                Some(metadata)
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(Error::Io(e));
+            }
+        };
+
+        if let Some(metadata) = &existing {
+            if options.skip_unchanged && metadata.len() == bytes.len() as u64 && std::fs::read(&target)? == bytes {
+                if let Some(batch) = batch {
+                    self.commit_prepared(batch);
+                }
+                return Ok(SaveOutcome::Unchanged);
+            }
+        }
+
+        let dir: PathBuf = match target.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from(".")
+        };
+        let name: String = match target.file_name() {
+            Some(n) => n.to_string_lossy().to_string(),
+            None => {
+                return Err(Error::InvalidTarget(format!("'{}' is not a file name", target.display())));
+            }
+        };
+        let (temporary, file) = create_temporary(&dir, &name)?;
+        if let Err(e) = replace_with(&temporary, file, &bytes, &target, existing.as_ref(), options.backup) {
+            let _ = std::fs::remove_file(&temporary);
+            return Err(e);
+        }
+
+        // So that the rename itself survives a crash, not all filesystems allow this.
+        #[cfg(unix)]
+        if let Ok(d) = File::open(&dir) {
+            let _ = d.sync_all();
+        }
+        if let Some(batch) = batch {
+            self.commit_prepared(batch);
+        }
+        Ok(SaveOutcome::Written)
+    }
+}
@@ -0,0 +1,221 @@
+//! A small path language for picking elements from a question more precisely than by
+//! their names alone.
+//!
+//! A selector is a list of steps separated by `/` for direct children and `//` for
+//! any descendants. The first step looks at all the descendants of the question, unless
+//! the selector starts with `/` in which case only its direct children. Each step names
+//! an element, or `*` for any, and may have predicates:
+//!
+//!  - `[@format=html]` the attribute has that value, `[@format]` the attribute exists.
+//!  - `[name=prt1]` the element has a child element with that text content.
+//!  - `[2]` the third of the elements matched so far under the same parent. Unlike in XPath
+//!    the counting starts from 0, like with question indices, so `node[0]` is the first node.
+//!    Also `//node[0]` gives the first node under each parent, not just the first one overall.
+//!
+//! Values may be quoted with `"` or `'` if they contain `]` or need surrounding spaces.
+//! For example `prt[name=prt1]/node[0]/truefeedback/text` or `input[name=ans1]/tans`.
+
+use std::collections::HashMap;
+use crate::{ContentType, Error, QParser};
+
+/// How a step moves from the elements matched by the previous one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+    Child,
+    Descendant
+}
+
+/// A condition on the elements a step matches.
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    /// The attribute exists, and has the value if one is given.
+    Attribute(String, Option<String>),
+    /// A child element with the text content.
+    Child(String, String),
+    /// The position among the matching siblings.
+    Index(usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    axis: Axis,
+    /// The local name of the element, `*` for any.
+    name: String,
+    predicates: Vec<Predicate>
+}
+
+/// A parsed selector.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Selector {
+    steps: Vec<Step>
+}
+
+/// Reads characters that can be part of a name.
+fn read_name(chars: &[char], pos: &mut usize) -> String {
+    let start: usize = *pos;
+    while *pos < chars.len() && (chars[*pos].is_alphanumeric() || "_-.:*".contains(chars[*pos])) {
+        *pos += 1;
+    }
+    chars[start..*pos].iter().collect()
+}
+
+/// Reads a value, quoted or until the closing `]`.
+fn read_value(chars: &[char], pos: &mut usize, selector: &str) -> Result<String, Error> {
+    if *pos < chars.len() && (chars[*pos] == '"' || chars[*pos] == '\'') {
+        let quote: char = chars[*pos];
+        let start: usize = *pos + 1;
+        match chars[start..].iter().position(|c| *c == quote) {
+            Some(length) => {
+                *pos = start + length + 1;
+                Ok(chars[start..start + length].iter().collect())
+            },
+            None => Err(Error::InvalidSelector(format!("unclosed quote in '{selector}'")))
+        }
+    } else {
+        let start: usize = *pos;
+        while *pos < chars.len() && chars[*pos] != ']' {
+            *pos += 1;
+        }
+        Ok(chars[start..*pos].iter().collect::<String>().trim().to_string())
+    }
+}
+
+impl Selector {
+    /// Parses a selector, the error tells what is wrong with it.
+    pub(crate) fn parse(selector: &str) -> Result<Selector, Error> {
+        let chars: Vec<char> = selector.trim().chars().collect();
+        let invalid = |reason: &str| Error::InvalidSelector(format!("{reason} in '{selector}'"));
+        let mut steps: Vec<Step> = Vec::new();
+        let mut pos: usize = 0;
+        let mut axis: Axis = Axis::Descendant;
+        if chars.starts_with(&['/', '/']) {
+            pos = 2;
+        } else if chars.starts_with(&['/']) {
+            axis = Axis::Child;
+            pos = 1;
+        }
+        loop {
+            let name: String = read_name(&chars, &mut pos);
+            if name.is_empty() || (name.contains('*') && name != "*") {
+                return Err(invalid(&format!("bad element name at {pos}")));
+            }
+            let mut predicates: Vec<Predicate> = Vec::new();
+            while pos < chars.len() && chars[pos] == '[' {
+                pos += 1;
+                let predicate: Predicate = if pos < chars.len() && chars[pos] == '@' {
+                    pos += 1;
+                    let attribute: String = read_name(&chars, &mut pos);
+                    if attribute.is_empty() {
+                        return Err(invalid(&format!("bad attribute name at {pos}")));
+                    }
+                    if pos < chars.len() && chars[pos] == '=' {
+                        pos += 1;
+                        Predicate::Attribute(attribute, Some(read_value(&chars, &mut pos, selector)?))
+                    } else {
+                        Predicate::Attribute(attribute, None)
+                    }
+                } else if pos < chars.len() && chars[pos].is_ascii_digit() {
+                    let start: usize = pos;
+                    while pos < chars.len() && chars[pos].is_ascii_digit() {
+                        pos += 1;
+                    }
+                    match chars[start..pos].iter().collect::<String>().parse::<usize>() {
+                        Ok(index) => Predicate::Index(index),
+                        Err(_) => {
+                            return Err(invalid(&format!("bad index at {start}")));
+                        }
+                    }
+                } else {
+                    let child: String = read_name(&chars, &mut pos);
+                    if child.is_empty() || pos >= chars.len() || chars[pos] != '=' {
+                        return Err(invalid(&format!("bad predicate at {pos}")));
+                    }
+                    pos += 1;
+                    Predicate::Child(child, read_value(&chars, &mut pos, selector)?)
+                };
+                if pos >= chars.len() || chars[pos] != ']' {
+                    return Err(invalid(&format!("expected ']' at {pos}")));
+                }
+                pos += 1;
+                predicates.push(predicate);
+            }
+            steps.push(Step {
+                axis,
+                name,
+                predicates
+            });
+
+            if pos == chars.len() {
+                break;
+            } else if chars[pos..].starts_with(&['/', '/']) {
+                axis = Axis::Descendant;
+                pos += 2;
+            } else if chars[pos] == '/' {
+                axis = Axis::Child;
+                pos += 1;
+            } else {
+                return Err(invalid(&format!("unexpected '{}' at {pos}", chars[pos])));
+            }
+        }
+        Ok(Selector { steps })
+    }
+
+    /// The elements under the given one the selector matches, in document order.
+    pub(crate) fn evaluate<'a, 'input>(&self, root: roxmltree::Node<'a, 'input>) -> Vec<roxmltree::Node<'a, 'input>> {
+        let mut context: Vec<roxmltree::Node> = vec![root];
+        for step in &self.steps {
+            let mut next: Vec<roxmltree::Node> = Vec::new();
+            for node in &context {
+                let candidates: Vec<roxmltree::Node> = match step.axis {
+                    Axis::Child => node.children().filter(|n| n.is_element()).collect(),
+                    Axis::Descendant => node.descendants().skip(1).filter(|n| n.is_element()).collect()
+                };
+                let mut matched: Vec<roxmltree::Node> = candidates.into_iter().filter(|n| step.name == "*" || n.tag_name().name() == step.name).collect();
+                for predicate in &step.predicates {
+                    matched = match predicate {
+                        Predicate::Index(i) => {
+                            // Counted separately under each parent.
+                            let mut counts: HashMap<roxmltree::NodeId, usize> = HashMap::new();
+                            matched.into_iter().filter(|n| {
+                                let count: &mut usize = counts.entry(n.parent().map_or(n.id(), |p| p.id())).or_insert(0);
+                                *count += 1;
+                                *count == *i + 1
+                            }).collect()
+                        },
+                        Predicate::Attribute(name, value) => matched.into_iter().filter(|n| match (n.attribute(name.as_str()), value) {
+                            (Some(found), Some(expected)) => found == expected,
+                            (found, None) => found.is_some(),
+                            (None, _) => false
+                        }).collect(),
+                        Predicate::Child(name, value) => matched.into_iter().filter(|n| n.children().any(|c| {
+                            c.is_element() && c.tag_name().name() == name && text_content(c).trim() == value
+                        })).collect()
+                    };
+                }
+                next.extend(matched);
+            }
+            // Descendant steps may reach the same element from multiple directions.
+            next.sort_by_key(|n| n.range().start);
+            next.dedup();
+            context = next;
+        }
+        context
+    }
+}
+
+/// The text inside the element and its children, entities and CDATA decoded.
+fn text_content(node: roxmltree::Node) -> String {
+    node.descendants().filter(|n| n.is_text()).map(|n| n.text().unwrap_or_default()).collect()
+}
+
+impl QParser {
+    /// Searches elements matching a selector from within a singular question, see the
+    /// module documentation for the syntax. The elements are described like `get_elements()`
+    /// describes them.
+    pub fn select(&mut self, qnum: usize, selector: &str) -> Result<Vec<ContentType>, Error> {
+        let selector: Selector = Selector::parse(selector)?;
+        self.with_question(qnum, |parser, node, base| {
+            selector.evaluate(node).into_iter().map(|n| parser._get_element(n, base)).collect()
+        })
+    }
+}
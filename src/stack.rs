@@ -3,7 +3,7 @@
 //! Note that this tooling is for the question-xml format of a relatively 
 //! recent STACK in this case ~4.8.0
 
-use crate::{ContentRef, ContentType, QParser};
+use crate::{ContentRef, ContentType, QParser, Error};
 use std::collections::HashMap;
 
 /// STACK specific struct for working with inputs.
@@ -151,17 +151,17 @@ impl STACKQuestion {
 	/// A function to fetch references to all content of a given type. In this case,
 	/// all the CASText fields with formatting on the editor side.
 	pub fn get_castext_fields(&self) -> Vec<(STACKPath, ContentType)> {
-		let mut result: Vec<(STACKPath, ContentType)> = Vec::new();
-
 		// Items of the root.
-		result.push((STACKPath::Root("questiontext".to_string()), self.questiontext.clone()));
-		result.push((STACKPath::Root("generalfeedback".to_string()), self.generalfeedback.clone()));
-		result.push((STACKPath::Root("specificfeedback".to_string()), self.specificfeedback.clone()));
-		result.push((STACKPath::Root("questionnote".to_string()), self.questionnote.clone()));
-		result.push((STACKPath::Root("questiondescription".to_string()), self.questiondescription.clone()));
-		result.push((STACKPath::Root("prtcorrect".to_string()), self.prtcorrect.clone()));
-		result.push((STACKPath::Root("prtpartiallycorrect".to_string()), self.prtpartiallycorrect.clone()));
-		result.push((STACKPath::Root("prtincorrect".to_string()), self.prtincorrect.clone()));
+		let mut result: Vec<(STACKPath, ContentType)> = vec![
+			(STACKPath::Root("questiontext".to_string()), self.questiontext.clone()),
+			(STACKPath::Root("generalfeedback".to_string()), self.generalfeedback.clone()),
+			(STACKPath::Root("specificfeedback".to_string()), self.specificfeedback.clone()),
+			(STACKPath::Root("questionnote".to_string()), self.questionnote.clone()),
+			(STACKPath::Root("questiondescription".to_string()), self.questiondescription.clone()),
+			(STACKPath::Root("prtcorrect".to_string()), self.prtcorrect.clone()),
+			(STACKPath::Root("prtpartiallycorrect".to_string()), self.prtpartiallycorrect.clone()),
+			(STACKPath::Root("prtincorrect".to_string()), self.prtincorrect.clone())
+		];
 
 		// Then the PRT-nodes.
 		for (prtname, prt) in self.prts.clone().into_iter() {
@@ -170,7 +170,7 @@ impl STACKQuestion {
 				result.push((STACKPath::PRTNode(prtname.clone(), i, "falsefeedback".to_string()), prt.nodes[i].falsefeedback.clone()));
 			}
 		}
-		return result;
+		result
 	}
 
 	/// A function to fetch references to all content of a given type. In this case,
@@ -185,7 +185,7 @@ impl STACKQuestion {
 		for (prtname, prt) in self.prts.clone().into_iter() {
 			result.push((STACKPath::PRT(prtname.clone(), "feedbackvariables".to_string()), prt.feedbackvariables.clone()));
 		}
-		return result;
+		result
 	}
 
 	/// A function to fetch references to all content of a given type. In this case,
@@ -218,58 +218,60 @@ impl STACKQuestion {
 			}	
 		}
 
-		return result;
+		result
 	}
 }
 
 
 
 
+/// Turns a missing field into an error naming it.
+fn required<T>(value: Option<T>, name: &str) -> Result<T, Error> {
+	value.ok_or(Error::MissingElement(name.to_string()))
+}
+
+/// Turns something found where it should not be into an error naming it.
+fn unexpected(content: &ContentType) -> Error {
+	Error::UnexpectedElement(match content {
+		ContentType::AttributeValue(name, _) |
+		ContentType::Element(name, _, _) |
+		ContentType::MoodleTextElement(name, _, _) |
+		ContentType::ProcessingInstruction(name, _, _) => name.clone(),
+		ContentType::ElementContent(_) => "#text".to_string(),
+		ContentType::Comment(_, _) => "#comment".to_string()
+	})
+}
+
 impl QParser {
 	/// Type specific extraction of questions
+	///
+	/// Panics if the question does not exist, is not a STACK question or is missing parts,
+	/// see `try_get_as_stack_question()`.
 	pub fn get_as_stack_question(&mut self, qnum: usize) -> STACKQuestion {
-		self.execute_changes();
-
-        let opt = roxmltree::ParsingOptions {
-            allow_dtd: true,
-            ..roxmltree::ParsingOptions::default()
-        };
-        let binding = self.content.clone();
-        let doc = match roxmltree::Document::parse_with_options(&binding, opt) {
-            Ok(doc) => doc,
-            Err(e) => {
-                panic!("Error: {}.", e);
-            }
-        };
-
-        let mut qn: usize = 0;
-        for node in doc.descendants() {
-            if node.is_element() && node.tag_name().name() == "question" {
-                match node.attribute("type") {
-                    Some(qtype) => {
-                        if qtype == "category" {
-                            // We ignore these.
-                            continue;
-                        }
-                        if qn == qnum {
-        					if qtype != "stack" {
-        						panic!("Was expecting a 'stack' question, found '{}' instead.", qtype);
-        					} 				
-        					return self.into_stack_question(node);
-                        }
-                        qn = qn + 1;
-                    },
-                    None => {
-                        // Ignore here.
-                    }
-                }
-            }
-        }
-        panic!("{}",format!("Only {qn} questions, but was trying to get index {qnum}."));
+		match self.try_get_as_stack_question(qnum) {
+			Ok(question) => question,
+			Err(e) => panic!("{}", e)
+		}
+	}
+
+	/// Type specific extraction of questions, with errors describing what was wrong
+	/// with the question, if something was.
+	pub fn try_get_as_stack_question(&mut self, qnum: usize) -> Result<STACKQuestion, Error> {
+		self.with_question(qnum, |parser, node, base| {
+			let qtype = node.attribute("type").unwrap_or_default();
+			if qtype != "stack" {
+				return Err(Error::WrongQuestionType {
+					index: qnum,
+					expected: "stack".to_string(),
+					found: qtype.to_string()
+				});
+			}
+			parser.to_stack_question(node, base)
+		})
 	}
 
 	/// Internal logic for the top level of a STACK question.
-	fn into_stack_question(&mut self, node: roxmltree::Node) -> STACKQuestion {
+	fn to_stack_question(&self, node: roxmltree::Node, base: usize) -> Result<STACKQuestion, Error> {
 		let mut name: Option<ContentRef> = None;
 		let mut questiontext: Option<ContentType> = None;
 		let mut generalfeedback: Option<ContentType> = None;
@@ -327,13 +329,13 @@ impl QParser {
 			"logicsymbol".to_string(),
 			"matrixparens".to_string(),
 			"variantsselectionseed".to_string()
-		]);
+		], base)?;
 
 		for el in elems {
 			match el {
 				ContentType::Element(nam, _, attr_contents) => {
 					// These should not have inner struct.
-					if let ContentType::ElementContent(c) = attr_contents.last().unwrap() {
+					if let Some(ContentType::ElementContent(c)) = attr_contents.last() {
 						let cref = Some(c.clone());
 						match nam.as_str() {
 							"defaultgrade" => { defaultgrade = cref; }
@@ -352,7 +354,7 @@ impl QParser {
 							"logicsymbol" => { logicsymbol = cref; }
 							"matrixparens" => { matrixparens = cref; }
 							"variantsselectionseed" => { variantsselectionseed = cref; }
-							_ => { panic!("Unexpected Element."); }
+							_ => { return Err(Error::UnexpectedElement(nam)); }
 						}
 					}
 				},
@@ -366,11 +368,11 @@ impl QParser {
 						"prtcorrect" => { prtcorrect = Some(el); }
 						"prtpartiallycorrect" => { prtpartiallycorrect = Some(el); }
 						"prtincorrect" => { prtincorrect = Some(el); }
-						_ => { panic!("Unexpected MoodleTextElement."); }
+						_ => { return Err(Error::UnexpectedElement(nam.clone())); }
 					}
 				},
-				_ => {
-					panic!("Unexpected type.");
+				other => {
+					return Err(unexpected(&other));
 				}
 			}
 		}
@@ -382,10 +384,9 @@ impl QParser {
 				n.tag_name().name() == "stackversion" ||
 				n.tag_name().name() == "questionvariables") {
 				// Pick the inner text of these.
-				let text = &self._get_elements(n, vec!["text".to_string()])[0];
+				let text = required(self._get_elements(n, vec!["text".to_string()], base)?.into_iter().next(), "text")?;
                 if let ContentType::Element(_,_,items) = text {
-                    let econtent = &items[0];
-                    if let ContentType::ElementContent(c) = econtent {
+                    if let Some(ContentType::ElementContent(c)) = items.last() {
                         let cref = Some(c.clone());
                         match n.tag_name().name() {
                         	"name" => { name = cref; },
@@ -396,52 +397,52 @@ impl QParser {
                     }
                 }
 			} else if n.is_element() && n.tag_name().name() == "input" {
-				let input = self.into_stack_input(n);
+				let input = self.to_stack_input(n, base)?;
 				inputs.insert(input.name.content.clone(), input);
 			} else if n.is_element() && n.tag_name().name() == "prt" {
-				let prt = self.into_stack_prt(n);
+				let prt = self.to_stack_prt(n, base)?;
 				prts.insert(prt.name.content.clone(), prt);
 			} else if n.is_element() && n.tag_name().name() == "qtest" {
-				let test = self.into_stack_qtest(n);
+				let test = self.to_stack_qtest(n, base)?;
 				tests.push(test);
 			}
 		}
 
-		STACKQuestion {
-			name: name.expect("Missing 'name' element."),
-			questiontext: questiontext.expect("Missing 'questiontext' element."),
-			generalfeedback: generalfeedback.expect("Missing 'generalfeedback' element."),
-			defaultgrade: defaultgrade.expect("Missing 'defaultgrade' element."),
-			penalty: penalty.expect("Missing 'penalty' element."),
-			hidden: hidden.expect("Missing 'hidden' element."),
-			idnumber: idnumber.expect("Missing 'idnumber' element."),
-			stackversion: stackversion.expect("Missing 'stackversion' element."),
-			questionvariables: questionvariables.expect("Missing 'questionvariables' element."),
-			specificfeedback: specificfeedback.expect("Missing 'specificfeedback' element."),
-			questionnote: questionnote.expect("Missing 'questionnote' element."),
-			questiondescription: questiondescription.expect("Missing 'questiondescription' element."),
-			questionsimplify: questionsimplify.expect("Missing 'questionsimplify' element."),
-			assumepositive: assumepositive.expect("Missing 'assumepositive' element."),
-			assumereal: assumereal.expect("Missing 'assumereal' element."),
-			prtcorrect: prtcorrect.expect("Missing 'prtcorrect' element."),
-			prtpartiallycorrect: prtpartiallycorrect.expect("Missing 'prtpartiallycorrect' element."),
-			prtincorrect: prtincorrect.expect("Missing 'prtincorrect' element."),
-			decimals: decimals.expect("Missing 'decimals' element."),
-			scientificnotation: scientificnotation.expect("Missing 'scientificnotation' element."),
-			multiplicationsign: multiplicationsign.expect("Missing 'multiplicationsign' element."),
-			sqrtsign: sqrtsign.expect("Missing 'sqrtsign' element."),
-			complexno: complexno.expect("Missing 'complexno' element."),
-			inversetrig: inversetrig.expect("Missing 'inversetrig' element."),
-			logicsymbol: logicsymbol.expect("Missing 'logicsymbol' element."),
-			matrixparens: matrixparens.expect("Missing 'matrixparens' element."),
-			variantsselectionseed: variantsselectionseed.expect("Missing 'variantsselectionseed' element."),
-			inputs: inputs,
-			prts: prts,
-			tests: tests
-		}
+		Ok(STACKQuestion {
+			name: required(name, "name")?,
+			questiontext: required(questiontext, "questiontext")?,
+			generalfeedback: required(generalfeedback, "generalfeedback")?,
+			defaultgrade: required(defaultgrade, "defaultgrade")?,
+			penalty: required(penalty, "penalty")?,
+			hidden: required(hidden, "hidden")?,
+			idnumber: required(idnumber, "idnumber")?,
+			stackversion: required(stackversion, "stackversion")?,
+			questionvariables: required(questionvariables, "questionvariables")?,
+			specificfeedback: required(specificfeedback, "specificfeedback")?,
+			questionnote: required(questionnote, "questionnote")?,
+			questiondescription: required(questiondescription, "questiondescription")?,
+			questionsimplify: required(questionsimplify, "questionsimplify")?,
+			assumepositive: required(assumepositive, "assumepositive")?,
+			assumereal: required(assumereal, "assumereal")?,
+			prtcorrect: required(prtcorrect, "prtcorrect")?,
+			prtpartiallycorrect: required(prtpartiallycorrect, "prtpartiallycorrect")?,
+			prtincorrect: required(prtincorrect, "prtincorrect")?,
+			decimals: required(decimals, "decimals")?,
+			scientificnotation: required(scientificnotation, "scientificnotation")?,
+			multiplicationsign: required(multiplicationsign, "multiplicationsign")?,
+			sqrtsign: required(sqrtsign, "sqrtsign")?,
+			complexno: required(complexno, "complexno")?,
+			inversetrig: required(inversetrig, "inversetrig")?,
+			logicsymbol: required(logicsymbol, "logicsymbol")?,
+			matrixparens: required(matrixparens, "matrixparens")?,
+			variantsselectionseed: required(variantsselectionseed, "variantsselectionseed")?,
+			inputs,
+			prts,
+			tests
+		})
 	}
 
-	fn into_stack_input(&mut self, node: roxmltree::Node) -> STACKInput {
+	fn to_stack_input(&self, node: roxmltree::Node, base: usize) -> Result<STACKInput, Error> {
 		let mut name: Option<ContentRef> = None;
 		let mut r#type: Option<ContentRef> = None;
 		let mut tans: Option<ContentRef> = None;
@@ -476,13 +477,13 @@ impl QParser {
 			"mustverify".to_string(),
 			"showvalidation".to_string(),
 			"options".to_string()
-		]);
+		], base)?;
 
 		for el in elems {
 			match el {
 				ContentType::Element(nam, _, attr_contents) => {
 					// These should not have inner struct.
-					if let ContentType::ElementContent(c) = attr_contents.last().unwrap() {
+					if let Some(ContentType::ElementContent(c)) = attr_contents.last() {
 						let cref = Some(c.clone());
 						match nam.as_str() {
 							"name" => { name = cref; },
@@ -501,35 +502,35 @@ impl QParser {
 							"mustverify" => { mustverify = cref; },
 							"showvalidation" => { showvalidation = cref; },
 							"options" => { options = cref; },
-							_ => { panic!("Unexpected Element."); }
+							_ => { return Err(Error::UnexpectedElement(nam)); }
 						}
 					}
 				},
-				_ => { panic!("Unexpected type.");}
+				other => { return Err(unexpected(&other)); }
 			}
 		}
 
-		STACKInput {
-		    name: name.expect("Missing name element."),
-		    r#type: r#type.expect("Missing type element."),
-		    tans: tans.expect("Missing tans element."),
-		    boxsize: boxsize.expect("Missing boxsize element."),
-		    strictsyntax: strictsyntax.expect("Missing strictsyntax element."),
-		    insertstars: insertstars.expect("Missing insertstars element."),
-		    syntaxhint: syntaxhint.expect("Missing syntaxhint element."),
-		    syntaxattribute: syntaxattribute.expect("Missing syntaxattribute element."),
-		    forbidwords: forbidwords.expect("Missing forbidwords element."),
-		    allowwords: allowwords.expect("Missing allowwords element."),
-		    forbidfloat: forbidfloat.expect("Missing forbidfloat element."),
-		    requirelowestterms: requirelowestterms.expect("Missing requirelowestterms element."),
-		    checkanswertype: checkanswertype.expect("Missing checkanswertype element."),
-		    mustverify: mustverify.expect("Missing mustverify element."),
-		    showvalidation: showvalidation.expect("Missing showvalidation element."),
-		    options: options.expect("Missing options element.")
-		}
+		Ok(STACKInput {
+		    name: required(name, "name")?,
+		    r#type: required(r#type, "type")?,
+		    tans: required(tans, "tans")?,
+		    boxsize: required(boxsize, "boxsize")?,
+		    strictsyntax: required(strictsyntax, "strictsyntax")?,
+		    insertstars: required(insertstars, "insertstars")?,
+		    syntaxhint: required(syntaxhint, "syntaxhint")?,
+		    syntaxattribute: required(syntaxattribute, "syntaxattribute")?,
+		    forbidwords: required(forbidwords, "forbidwords")?,
+		    allowwords: required(allowwords, "allowwords")?,
+		    forbidfloat: required(forbidfloat, "forbidfloat")?,
+		    requirelowestterms: required(requirelowestterms, "requirelowestterms")?,
+		    checkanswertype: required(checkanswertype, "checkanswertype")?,
+		    mustverify: required(mustverify, "mustverify")?,
+		    showvalidation: required(showvalidation, "showvalidation")?,
+		    options: required(options, "options")?
+		})
 	}
 
-	fn into_stack_prt(&mut self, node: roxmltree::Node) -> STACKPrt {
+	fn to_stack_prt(&self, node: roxmltree::Node, base: usize) -> Result<STACKPrt, Error> {
 	    let mut name: Option<ContentRef> = None;
 	    let mut value: Option<ContentRef> = None;
 	    let mut autosimplify: Option<ContentRef> = None;
@@ -543,61 +544,60 @@ impl QParser {
 			"value".to_string(),
 			"autosimplify".to_string(),
 			"feedbackstyle".to_string()
-		]);
+		], base)?;
 
 		for el in elems {
 			match el {
 				ContentType::Element(nam, _, attr_contents) => {
 					// These should not have inner struct.
-					if let ContentType::ElementContent(c) = attr_contents.last().unwrap() {
+					if let Some(ContentType::ElementContent(c)) = attr_contents.last() {
 						let cref = Some(c.clone());
 						match nam.as_str() {
 							"name" => { 
 								// Here we have a bit of a problem as there is a <name> inside
 								// the nodes and we don't restrict the search to direct children
 								// but the library returns stuff in document order so.
-								if name == None {
+								if name.is_none() {
 									name = cref;
 								}
 							},
 							"value" => { value = cref; },
 							"autosimplify" => { autosimplify = cref; },
 							"feedbackstyle" => { feedbackstyle = cref; },
-							_ => { panic!("Unexpected Element."); }
+							_ => { return Err(Error::UnexpectedElement(nam)); }
 						}
 					}
 				},
-				_ => { panic!("Unexpected type.");}
+				other => { return Err(unexpected(&other)); }
 			}
 		}
 
 		// Then the <text> and nodes.
 		for n in node.children() {
 			if n.is_element() && n.tag_name().name() == "node" {
-				nodes.push(self.into_stack_prt_node(n));
+				nodes.push(self.to_stack_prt_node(n, base)?);
 			} else if n.is_element() && n.tag_name().name() == "feedbackvariables" {
-				let text = &self._get_elements(n, vec!["text".to_string()])[0];
+				let text = required(self._get_elements(n, vec!["text".to_string()], base)?.into_iter().next(), "text")?;
                 if let ContentType::Element(_,_,items) = text {
-                    let econtent = &items[0];
-                    if let ContentType::ElementContent(c) = econtent {
+                    if let Some(ContentType::ElementContent(c)) = items.last() {
                 		feedbackvariables = Some(c.clone());
                     }
                 }
 			}
 		}
 
-		STACKPrt {
-		    name: name.expect("Missing name element."),
-		    value: value.expect("Missing value element."),
-		    autosimplify: autosimplify.expect("Missing autosimplify element."),
-		    feedbackstyle: feedbackstyle.expect("Missing feedbackstyle element."),
-		    feedbackvariables: feedbackvariables.expect("Missing feedbackvariables element."),
-		   	nodes: nodes
-		}
+		Ok(STACKPrt {
+		    name: required(name, "name")?,
+		    value: required(value, "value")?,
+		    autosimplify: required(autosimplify, "autosimplify")?,
+		    feedbackstyle: required(feedbackstyle, "feedbackstyle")?,
+		    feedbackvariables: required(feedbackvariables, "feedbackvariables")?,
+		   	nodes
+		})
 	}
 
 
-	fn into_stack_prt_node(&mut self, node: roxmltree::Node) -> STACKPrtNode {
+	fn to_stack_prt_node(&self, node: roxmltree::Node, base: usize) -> Result<STACKPrtNode, Error> {
 	    let mut name: Option<ContentRef> = None;
 	    let mut answertest: Option<ContentRef> = None;
 	    let mut sans: Option<ContentRef> = None;
@@ -638,14 +638,14 @@ impl QParser {
 		    "falsenextnode".to_string(),
 		    "falseanswernote".to_string(),
 		    "falsefeedback".to_string()
-		]);
+		], base)?;
 
 
 		for el in elems {
 			match el {
 				ContentType::Element(nam, _, attr_contents) => {
 					// These should not have inner struct.
-					if let ContentType::ElementContent(c) = attr_contents.last().unwrap() {
+					if let Some(ContentType::ElementContent(c)) = attr_contents.last() {
 						let cref = Some(c.clone());
 						match nam.as_str() {
 						    "name" => { name = cref; }
@@ -664,7 +664,7 @@ impl QParser {
 						    "falsepenalty" => { falsepenalty = cref; }
 						    "falsenextnode" => { falsenextnode = cref; }
 						    "falseanswernote" => { falseanswernote = cref; }
-							_ => { panic!("Unexpected Element."); }
+							_ => { return Err(Error::UnexpectedElement(nam)); }
 						}
 					}
 				},
@@ -672,38 +672,38 @@ impl QParser {
 					match nam.as_str() {
 						"truefeedback" => { truefeedback = Some(el); }
 						"falsefeedback" => { falsefeedback = Some(el); }
-						_ => { panic!("Unexpected MoodleTextElement."); }
+						_ => { return Err(Error::UnexpectedElement(nam.clone())); }
 					}
 				},
-				_ => {
-					panic!("Unexpected type.");
+				other => {
+					return Err(unexpected(&other));
 				}
 			}
 		}
 
-		STACKPrtNode {
-		    name: name.expect("Missing name element."),
-		    answertest: answertest.expect("Missing answertest element."),
-		    sans: sans.expect("Missing sans element."),
-		    tans: tans.expect("Missing tans element."),
-		    testoptions: testoptions.expect("Missing testoptions element."),
-		    quiet: quiet.expect("Missing quiet element."),
-		    truescoremode: truescoremode.expect("Missing truescoremode element."),
-		    truescore: truescore.expect("Missing truescore element."),
-		    truepenalty: truepenalty.expect("Missing truepenalty element."),
-		    truenextnode: truenextnode.expect("Missing truenextnode element."),
-		    trueanswernote: trueanswernote.expect("Missing trueanswernote element."),
-		    truefeedback: truefeedback.expect("Missing truefeedback element."),
-		    falsescoremode: falsescoremode.expect("Missing falsescoremode element."),
-		    falsescore: falsescore.expect("Missing falsescore element."),
-		    falsepenalty: falsepenalty.expect("Missing falsepenalty element."),
-		    falsenextnode: falsenextnode.expect("Missing falsenextnode element."),
-		    falseanswernote: falseanswernote.expect("Missing falseanswernote element."),
-		    falsefeedback: falsefeedback.expect("Missing falsefeedback element.")
-		}
+		Ok(STACKPrtNode {
+		    name: required(name, "name")?,
+		    answertest: required(answertest, "answertest")?,
+		    sans: required(sans, "sans")?,
+		    tans: required(tans, "tans")?,
+		    testoptions: required(testoptions, "testoptions")?,
+		    quiet: required(quiet, "quiet")?,
+		    truescoremode: required(truescoremode, "truescoremode")?,
+		    truescore: required(truescore, "truescore")?,
+		    truepenalty: required(truepenalty, "truepenalty")?,
+		    truenextnode: required(truenextnode, "truenextnode")?,
+		    trueanswernote: required(trueanswernote, "trueanswernote")?,
+		    truefeedback: required(truefeedback, "truefeedback")?,
+		    falsescoremode: required(falsescoremode, "falsescoremode")?,
+		    falsescore: required(falsescore, "falsescore")?,
+		    falsepenalty: required(falsepenalty, "falsepenalty")?,
+		    falsenextnode: required(falsenextnode, "falsenextnode")?,
+		    falseanswernote: required(falseanswernote, "falseanswernote")?,
+		    falsefeedback: required(falsefeedback, "falsefeedback")?
+		})
 	}
 
-	fn into_stack_qtest(&mut self, node: roxmltree::Node) -> STACKQtest {
+	fn to_stack_qtest(&self, node: roxmltree::Node, base: usize) -> Result<STACKQtest, Error> {
 		let mut testcase: Option<ContentRef> = None;
     	let mut description: Option<ContentRef> = None;
 		let mut inputs: HashMap<String, STACKQtestInput> = HashMap::new();
@@ -713,77 +713,77 @@ impl QParser {
 		let elems = self._get_elements(node, vec![
 			"testcase".to_string(),
 			"description".to_string()
-		]);
+		], base)?;
 
 		for el in elems {
 			match el {
 				ContentType::Element(nam, _, attr_contents) => {
 					// These should not have inner struct.
-					if let ContentType::ElementContent(c) = attr_contents.last().unwrap() {
+					if let Some(ContentType::ElementContent(c)) = attr_contents.last() {
 						let cref = Some(c.clone());
 						match nam.as_str() {
 							"testcase" => { testcase = cref; },
 							"description" => { description = cref; },
-							_ => { panic!("Unexpected Element."); }
+							_ => { return Err(Error::UnexpectedElement(nam)); }
 						}
 					}
 				},
-				_ => { panic!("Unexpected type.");}
+				other => { return Err(unexpected(&other)); }
 			}
 		}
 
 		// Then the others.
 		for n in node.children() {
 			if n.is_element() && n.tag_name().name() == "testinput" {
-				let testinput = self.into_stack_qtest_input(n);
+				let testinput = self.to_stack_qtest_input(n, base)?;
 				inputs.insert(testinput.name.content.clone(), testinput);
 			} else if n.is_element() && n.tag_name().name() == "expected" {
-				let expectation = self.into_stack_qtest_expected(n);
+				let expectation = self.to_stack_qtest_expected(n, base)?;
 				expected.insert(expectation.name.content.clone(), expectation);
 			}	
 		}
 
-		STACKQtest {
-			testcase: testcase.expect("Missing testcase element."),
-			description: description.expect("Missing description element."),
-			inputs: inputs,
-			expected: expected
-		}
+		Ok(STACKQtest {
+			testcase: required(testcase, "testcase")?,
+			description: required(description, "description")?,
+			inputs,
+			expected
+		})
 	}
 
-	fn into_stack_qtest_input(&mut self, node: roxmltree::Node) -> STACKQtestInput {
+	fn to_stack_qtest_input(&self, node: roxmltree::Node, base: usize) -> Result<STACKQtestInput, Error> {
 		let mut name: Option<ContentRef> = None;
 		let mut value: Option<ContentRef> = None;
 
 		let elems = self._get_elements(node, vec![
 			"name".to_string(),
 			"value".to_string()
-		]);
+		], base)?;
 
 		for el in elems {
 			match el {
 				ContentType::Element(nam, _, attr_contents) => {
 					// These should not have inner struct.
-					if let ContentType::ElementContent(c) = attr_contents.last().unwrap() {
+					if let Some(ContentType::ElementContent(c)) = attr_contents.last() {
 						let cref = Some(c.clone());
 						match nam.as_str() {
 							"name" => { name = cref; },
 							"value" => { value = cref; },
-							_ => { panic!("Unexpected Element."); }
+							_ => { return Err(Error::UnexpectedElement(nam)); }
 						}
 					}
 				},
-				_ => { panic!("Unexpected type.");}
+				other => { return Err(unexpected(&other)); }
 			}
 		}
 
-		STACKQtestInput {
-		    name: name.expect("Missing name element."),
-		    value: value.expect("Missing value element.")
-		}
+		Ok(STACKQtestInput {
+		    name: required(name, "name")?,
+		    value: required(value, "value")?
+		})
 	}
 
-	fn into_stack_qtest_expected(&mut self, node: roxmltree::Node) -> STACKQtestExpected {
+	fn to_stack_qtest_expected(&self, node: roxmltree::Node, base: usize) -> Result<STACKQtestExpected, Error> {
 		let mut name: Option<ContentRef> = None;
 		let mut expectedscore: Option<ContentRef> = None;
 		let mut expectedpenalty: Option<ContentRef> = None;
@@ -794,32 +794,32 @@ impl QParser {
 			"expectedscore".to_string(),
 			"expectedpenalty".to_string(),
 			"expectedanswernote".to_string()
-		]);
+		], base)?;
 
 		for el in elems {
 			match el {
 				ContentType::Element(nam, _, attr_contents) => {
 					// These should not have inner struct.
-					if let ContentType::ElementContent(c) = attr_contents.last().unwrap() {
+					if let Some(ContentType::ElementContent(c)) = attr_contents.last() {
 						let cref = Some(c.clone());
 						match nam.as_str() {
 							"name" => { name = cref; },
 							"expectedscore" => { expectedscore = cref; },
 							"expectedpenalty" => { expectedpenalty = cref; },
 							"expectedanswernote" => { expectedanswernote = cref; },
-							_ => { panic!("Unexpected Element."); }
+							_ => { return Err(Error::UnexpectedElement(nam)); }
 						}
 					}
 				},
-				_ => { panic!("Unexpected type.");}
+				other => { return Err(unexpected(&other)); }
 			}
 		}
 
-		STACKQtestExpected {
-		    name: name.expect("Missing name element."),
-		    expectedscore: expectedscore.expect("Missing expectedscore element."),
-		    expectedpenalty: expectedpenalty.expect("Missing expectedpenalty element."),
-		    expectedanswernote: expectedanswernote.expect("Missing expectedanswernote element.")
-		}
+		Ok(STACKQtestExpected {
+		    name: required(name, "name")?,
+		    expectedscore: required(expectedscore, "expectedscore")?,
+		    expectedpenalty: required(expectedpenalty, "expectedpenalty")?,
+		    expectedanswernote: required(expectedanswernote, "expectedanswernote")?
+		})
 	}
 }
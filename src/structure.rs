@@ -0,0 +1,275 @@
+//! Operations that add to the structure of the document instead of just replacing content.
+//! These try to match the indentation and line-endings of the surroundings so that the
+//! result looks like something Moodle would have exported and the diff is only the added lines.
+
+use crate::{Change, ConflictPolicy, ContentRef, ContentType, Error, LineEnding, QParser, Question, escape_attribute, parse_options};
+
+/// Indents all but the first line of the given text, empty lines are left empty.
+fn reindent(text: &str, indent: &str, line_ending: &str) -> String {
+    let mut result: String = String::with_capacity(text.len());
+    for (i, line) in text.lines().enumerate() {
+        if i > 0 {
+            result.push_str(line_ending);
+            if !line.is_empty() {
+                result.push_str(indent);
+            }
+        }
+        result.push_str(line);
+    }
+    result
+}
+
+/// The element the reference to a whole element points to.
+fn find_element<'a, 'input>(doc: &'a roxmltree::Document<'input>, whole: &ContentRef) -> Result<roxmltree::Node<'a, 'input>, Error> {
+    match doc.descendants().find(|n| n.is_element() && n.range() == whole.range()) {
+        Some(n) => Ok(n),
+        None => Err(Error::InvalidTarget("no such element in the document".to_string()))
+    }
+}
+
+impl QParser {
+    /// The reference to the whole element, rebased to the current version, if the target
+    /// is an element, comment or processing instruction. Text-fields are found by their
+    /// format-attribute.
+    fn anchor(&self, target: &ContentType) -> Result<ContentRef, Error> {
+        match target {
+            ContentType::Element(_, whole, _) => self.rebase(whole),
+            ContentType::Comment(whole, _) => self.rebase(whole),
+            ContentType::ProcessingInstruction(_, whole, _) => self.rebase(whole),
+            ContentType::MoodleTextElement(_, format, _) => {
+                let format: ContentRef = self.rebase(format)?;
+                let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
+                match doc.descendants().find(|n| n.is_element() && n.attributes().any(|a| a.name() == "format" && a.range_value() == format.range())) {
+                    Some(n) => Ok(self.content_ref(n.range().start, n.range().end)),
+                    None => Err(Error::InvalidTarget("no such element in the document".to_string()))
+                }
+            },
+            _ => Err(Error::InvalidTarget("only elements, text-fields, comments and processing instructions can be used as anchors".to_string()))
+        }
+    }
+
+    /// Registers a change inserting new XML before the given element. The new XML will be
+    /// placed on its own line with the same indentation as the element, and any line-breaks
+    /// in it will be indented to match. So give the new XML without indentation of its own.
+    pub fn insert_before(&mut self, sibling: &ContentType, xml: String) -> Result<(), Error> {
+        let whole: ContentRef = self.anchor(sibling)?;
+        let line_ending: &str = self.line_ending_around(whole.start, whole.end).as_str();
+        let new_content: String = match self.indentation_at(whole.start) {
+            Some(indent) => format!("{}{line_ending}{indent}", reindent(&xml, indent, line_ending)),
+            None => xml
+        };
+        self.try_register_change(Change::new(self.insertion_point(whole.start), new_content))
+    }
+
+    /// Registers a change inserting new XML after the given element. Indentation as with
+    /// `insert_before()`.
+    pub fn insert_after(&mut self, sibling: &ContentType, xml: String) -> Result<(), Error> {
+        let whole: ContentRef = self.anchor(sibling)?;
+        let line_ending: &str = self.line_ending_around(whole.start, whole.end).as_str();
+        let new_content: String = match self.indentation_at(whole.start) {
+            Some(indent) => format!("{line_ending}{indent}{}", reindent(&xml, indent, line_ending)),
+            None => xml
+        };
+        self.try_register_change(Change::new(self.insertion_point(whole.end), new_content))
+    }
+
+    /// Registers a change inserting new XML as the last child of the given element. The
+    /// indentation is taken from the existing children, or if there are none it is guessed
+    /// from the indentation of the element and its parent. Empty-tags like `<tags/>` get
+    /// opened up to contain the new child, the changes doing that are shared by all the
+    /// children inserted in the same batch so they end up in the order they were registered.
+    pub fn insert_as_last_child(&mut self, parent: &ContentType, xml: String) -> Result<(), Error> {
+        let whole: ContentRef = self.anchor(parent)?;
+        let line_ending: &str = self.line_ending_around(whole.start, whole.end).as_str();
+        let mut changes: Vec<(Change, ConflictPolicy)> = Vec::new();
+        {
+            let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
+            let node = find_element(&doc, &whole)?;
+            let parent_indent: String = self.indentation_at(whole.start).unwrap_or_default().to_string();
+            let last = node.children().rfind(|n| !(n.is_text() && n.text().unwrap_or_default().trim().is_empty()));
+            match last {
+                Some(child) => {
+                    // After the last child with the same indentation.
+                    let indent: String = match self.indentation_at(child.range().start) {
+                        Some(i) => i.to_string(),
+                        None => format!("{parent_indent}{}", self.indentation_unit(node))
+                    };
+                    changes.push((Change::new(self.insertion_point(child.range().end), format!("{line_ending}{indent}{}", reindent(&xml, &indent, line_ending))), ConflictPolicy::Reject));
+                },
+                None => {
+                    let indent: String = format!("{parent_indent}{}", self.indentation_unit(node));
+                    // The children go between the open and the close, those get written
+                    // by changes touching the insertion point from both sides.
+                    let pos: usize = if self.content[..whole.end].ends_with("/>") {
+                        // An empty-tag needs to be opened up.
+                        let tag: &str = self.content[whole.start + 1..].split(|c: char| c.is_whitespace() || c == '/' || c == '>').next().unwrap_or_default();
+                        let open_end: usize = self.content[..whole.end - 2].trim_end().len();
+                        changes.push((Change::new(self.content_ref(open_end, whole.end - 1), ">".to_string()), ConflictPolicy::MergeIdentical));
+                        changes.push((Change::new(self.content_ref(whole.end - 1, whole.end), format!("{line_ending}{parent_indent}</{tag}>")), ConflictPolicy::MergeIdentical));
+                        whole.end - 1
+                    } else {
+                        // Only whitespace inside, replace it.
+                        let close: usize = self.content[..whole.end].rfind("</").unwrap();
+                        let open_end: usize = match node.first_child() {
+                            Some(c) => c.range().start,
+                            None => close
+                        };
+                        if open_end < close {
+                            changes.push((Change::new(self.content_ref(open_end, close), String::new()), ConflictPolicy::MergeIdentical));
+                        }
+                        let close_tag: String = self.content[close..whole.end].to_string();
+                        changes.push((Change::new(self.content_ref(close, whole.end), format!("{line_ending}{parent_indent}{close_tag}")), ConflictPolicy::MergeIdentical));
+                        close
+                    };
+                    changes.push((Change::new(self.insertion_point(pos), format!("{line_ending}{indent}{}", reindent(&xml, &indent, line_ending))), ConflictPolicy::Reject));
+                }
+            }
+        }
+        self.try_register_changes(changes)
+    }
+
+    /// Registers a change adding an attribute after the last attribute of the element,
+    /// quoted like the existing attributes are and the value escaped. Errors if the element
+    /// already has that attribute.
+    pub fn insert_attribute(&mut self, element: &ContentType, name: &str, value: &str) -> Result<(), Error> {
+        let whole: ContentRef = self.anchor(element)?;
+        let (pos, quote) = {
+            let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
+            let node = find_element(&doc, &whole)?;
+            if node.attributes().any(|a| a.name() == name) {
+                return Err(Error::InvalidTarget(format!("the element already has the attribute '{name}'")));
+            }
+            match node.attributes().next_back() {
+                Some(last) => {
+                    let attr_end: usize = last.range().end;
+                    (attr_end, self.content[..attr_end].chars().next_back().unwrap())
+                },
+                None => {
+                    // Right after the tag name.
+                    let name_end: usize = self.content[whole.start + 1..].find(|c: char| c.is_whitespace() || c == '/' || c == '>').unwrap() + whole.start + 1;
+                    (name_end, '"')
+                }
+            }
+        };
+        self.try_register_change(Change::new(self.insertion_point(pos), format!(" {name}={quote}{}{quote}", escape_attribute(value))))
+    }
+
+    /// Registers a change removing the attribute from the element, along with the
+    /// whitespace before it. Errors if the element does not have that attribute.
+    pub fn remove_attribute(&mut self, element: &ContentType, name: &str) -> Result<(), Error> {
+        let whole: ContentRef = self.anchor(element)?;
+        let (start, end) = {
+            let doc = roxmltree::Document::parse_with_options(&self.content, parse_options())?;
+            let node = find_element(&doc, &whole)?;
+            match node.attributes().find(|a| a.name() == name) {
+                Some(attr) => {
+                    let start: usize = self.content[..attr.range().start].trim_end().len();
+                    (start, attr.range().end)
+                },
+                None => {
+                    return Err(Error::InvalidTarget(format!("the element has no attribute '{name}'")));
+                }
+            }
+        };
+        self.try_register_change(Change::new(self.content_ref(start, end), String::new()))
+    }
+
+    /// Registers a change removing the element, and should the element be alone on its
+    /// line(s) also the indentation before it and the line-break after it. So that no empty
+    /// lines are left behind. Works for anything given as a reference to a whole element.
+    pub fn remove_element(&mut self, whole_element: &ContentRef) -> Result<(), Error> {
+        let whole: ContentRef = self.rebase(whole_element)?;
+        let (start, end) = self.line_extended_range(whole.start, whole.end);
+        self.try_register_change(Change::new(self.content_ref(start, end), String::new()))
+    }
+
+    /// Registers a change removing the question like `remove_element()` would. If asked
+    /// to, the `<!-- question: N -->`-comment before the question will be removed as well.
+    pub fn remove_question(&mut self, question: &Question, with_comment: bool) -> Result<(), Error> {
+        let whole: ContentRef = self.rebase(&question.whole_element)?;
+        let mut start: usize = whole.start;
+        if with_comment {
+            if let Some(comment_start) = self.preceding_question_comment(whole.start) {
+                start = comment_start;
+            }
+        }
+        let (start, end) = self.line_extended_range(start, whole.end);
+        self.try_register_change(Change::new(self.content_ref(start, end), String::new()))
+    }
+
+    /// The start of a `<!-- question: N -->`-comment directly before the position, only
+    /// whitespace is allowed between them.
+    pub(crate) fn preceding_question_comment(&self, pos: usize) -> Option<usize> {
+        let before: &str = self.content[..pos].trim_end();
+        if !before.ends_with("-->") {
+            return None;
+        }
+        let comment_start: usize = before.rfind("<!--")?;
+        if before[comment_start + 4..].trim_start().starts_with("question:") {
+            Some(comment_start)
+        } else {
+            None
+        }
+    }
+
+    /// If the range is alone on its line(s) extends it to cover the indentation before it
+    /// and the line-break after it.
+    pub(crate) fn line_extended_range(&self, start: usize, end: usize) -> (usize, usize) {
+        let line_start: usize = match self.indentation_at(start) {
+            Some(indent) => start - indent.len(),
+            None => {
+                return (start, end);
+            }
+        };
+        let rest: &str = &self.content[end..];
+        let trailing: usize = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        let after: &str = &rest[trailing..];
+        if after.starts_with("\r\n") {
+            (line_start, end + trailing + 2)
+        } else if after.starts_with('\n') {
+            (line_start, end + trailing + 1)
+        } else if after.is_empty() {
+            (line_start, self.content.len())
+        } else {
+            (start, end)
+        }
+    }
+
+    /// The line-ending in the whitespace separating the range from its neighbours, or
+    /// if there is none there the one most of the document uses. Documents assembled
+    /// from several sources may mix them.
+    pub(crate) fn line_ending_around(&self, start: usize, end: usize) -> LineEnding {
+        let before: &str = &self.content[..start];
+        let after: &str = &self.content[end..];
+        let surroundings: String = format!("{}{}", &before[before.trim_end().len()..], &after[..after.len() - after.trim_start().len()]);
+        LineEnding::detect(&surroundings).unwrap_or_else(|| self.line_ending())
+    }
+
+    /// The whitespace between the start of the line and the given position, None if
+    /// there is something else on that line before that position.
+    pub(crate) fn indentation_at(&self, pos: usize) -> Option<&str> {
+        let line_start: usize = self.content[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let indent: &str = &self.content[line_start..pos];
+        if indent.chars().all(|c| c == ' ' || c == '\t') {
+            Some(indent)
+        } else {
+            None
+        }
+    }
+
+    /// Guess the unit of indentation used around this element. Moodle uses two spaces.
+    pub(crate) fn indentation_unit(&self, node: roxmltree::Node) -> String {
+        let own = self.indentation_at(node.range().start);
+        let parent = node.parent_element().and_then(|p| self.indentation_at(p.range().start));
+        if let (Some(own), Some(parent)) = (own, parent) {
+            if own.len() > parent.len() && own.starts_with(parent) {
+                return own[parent.len()..].to_string();
+            }
+        }
+        if own.unwrap_or_default().contains('\t') {
+            "\t".to_string()
+        } else {
+            "  ".to_string()
+        }
+    }
+}
@@ -156,8 +156,8 @@ fn update_named_element_from_question() {
 }
 
 /// One can also target attributes. And change their values,
-/// remember to entity encode values though as we allow writing 
-/// anything.
+/// remember to entity encode values though as changes that
+/// would break the document are not executed.
 #[test]
 fn change_attribute_value_wrong_way() {
 	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
@@ -240,7 +240,10 @@ fn change_attribute_value_wrong_way() {
 
 			parser.execute_changes();
 
-			assert_eq!(parser.get_current_content(), target_data_a);
+			// That would not be valid XML, so nothing was executed
+			// and the changes wait in the buffer.
+			assert_ne!(parser.get_current_content(), target_data_a);
+			assert_eq!(parser.discard_changes().len(), 2);
 		},
 		_ => {
 			panic!("Wrong type found!");
@@ -264,7 +267,7 @@ fn change_attribute_value_correct_way() {
 <quiz>
   <question type=\"some\">
   	<name><text>Test question1</text></name>
-  	<foo bar='&amp;&apos;' baz='...'>TEST&</foo>
+  	<foo bar='&amp;&apos;' baz='...'>TEST&amp;</foo>
   </question>
 </quiz>
 ".to_string();
@@ -318,7 +321,7 @@ fn change_attribute_value_correct_way() {
 				// not entity escape anything, nor does it do CDATA processing.
 				let change: Change = Change::new(
 					content_ref.clone(),
-					"TEST&".to_string()
+					"TEST&amp;".to_string()
 				);
 
 				// That change will be registered to the parser,
@@ -497,3 +500,80 @@ fn whole_element_modification() {
 		}
 	}
 }
+
+/// Content may mix entities, character references and CDATA sections, exports
+/// from different Moodle versions use different styles. `decode()` gives the
+/// text a parser would see and `text_escaped_version()` writes text back without
+/// CDATA.
+#[test]
+fn decoding_and_escaping_text() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE quiz [<!ENTITY custom \"x\">]>
+<quiz>
+  <question type=\"some\">
+    <name><text>a &lt; b &amp;&#38; &#x3C;<![CDATA[<b>&amp;</b>]]>&#8364; &custom;</text></name>
+    <generalfeedback format=\"html\"><text>ä&amp;ä</text></generalfeedback>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE quiz [<!ENTITY custom \"x\">]>
+<quiz>
+  <question type=\"some\">
+    <name><text>a &lt; b &amp;&#38; &#x3C;<![CDATA[<b>&amp;</b>]]>&#8364; &custom;</text></name>
+    <generalfeedback format=\"html\"><text>&lt;p&gt;Tom &amp; \"Jerry\"&lt;/p&gt;</text></generalfeedback>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let questions: Vec<Question> = parser.find_questions();
+	// Entities declared in the DTD are left as they are.
+	assert_eq!(questions[0].name.decode(), "a < b && <<b>&amp;</b>\u{20AC} &custom;".to_string());
+
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["generalfeedback".to_string()]);
+	let content = elements[0].clone().get_content().expect("Has content");
+	assert_eq!(content.decode(), "ä&ä".to_string());
+	parser.register_change(Change::text_escaped_version(content, "<p>Tom & \"Jerry\"</p>".to_string()));
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.get_current_content(), target_data);
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["generalfeedback".to_string()]);
+	assert_eq!(elements[0].clone().get_content().expect("Has content").decode(), "<p>Tom & \"Jerry\"</p>".to_string());
+}
+
+/// When rewriting content one usually wants the new content written the same way
+/// as the old one was, `style_preserving_version()` looks at the old content and
+/// uses CDATA or entities like it did.
+#[test]
+fn style_preserving_replacement() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Plain</text></name>
+    <questiontext format=\"html\"><text><![CDATA[<p>Old</p>]]></text></questiontext>
+    <generalfeedback format=\"html\"><text>&lt;p&gt;Old&lt;/p&gt;</text></generalfeedback>
+    <answer><text>plain</text></answer>
+    <hint><text>plain</text></hint>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Still plain</text></name>
+    <questiontext format=\"html\"><text><![CDATA[<p>New</p>]]></text></questiontext>
+    <generalfeedback format=\"html\"><text>&lt;p&gt;New&lt;/p&gt;</text></generalfeedback>
+    <answer><text><![CDATA[a < b]]></text></answer>
+    <hint><text><![CDATA[a ]]]]><![CDATA[> b]]></text></hint>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let texts: Vec<ContentType> = parser.get_elements(0, vec!["text".to_string()]);
+	let values = ["Still plain", "<p>New</p>", "<p>New</p>", "a < b", "a ]]> b"];
+	for (text, value) in texts.into_iter().zip(values) {
+		let content = text.get_content().expect("Has content");
+		parser.register_change(Change::style_preserving_version(content, value.to_string()));
+	}
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.get_current_content(), target_data);
+}
@@ -205,15 +205,16 @@ fn trying_to_parse_a_broken_document() {
 	}
 }
 
-/// As we do not check the changes or if they lead to a broken
-/// document it is possible to not be able to search after one
-/// has broken a document.
+/// Changes that would lead to a broken document are not executed,
+/// they stay in the buffer and it is not possible to search while
+/// they are there.
 /// Likewise, this tool won't allow one to save the document into
-/// a file if one breaks it. Although if you intentionally break
+/// a file with them. Although if you intentionally break
 /// things you can always ask for the contents of the whole document
 /// and save it anywhere you wish.
-/// You just cannot continue using this toolset after the document 
-/// has been broken.
+/// You just cannot continue using this toolset until those changes
+/// have been discarded. `try_execute_changes()` tells which of them
+/// broke the document.
 #[test]
 #[should_panic]
 fn continuing_after_breaking_a_document() {
@@ -240,7 +241,7 @@ fn continuing_after_breaking_a_document() {
 
 			// For testing, lets replace that whoel element with something broken.
 			let change = Change::new(whole_element_ref.clone(), "<missing></part>".to_string());
-			// The system will happily execute that change.
+			// The system will not execute that change.
 			parser.register_change(change);
 			parser.execute_changes();
 
@@ -252,3 +253,341 @@ fn continuing_after_breaking_a_document() {
 		}
 	}
 }
+
+
+/// The `try_`-versions of the functions return `Error` values that tell
+/// what went wrong, so that processing many files can continue past odd ones.
+#[test]
+fn errors_instead_of_panics() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+  	<name><text>Test</text></name>
+  	<something>Some</something>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::try_from_string(data).expect("Valid input should not fail");
+
+	match parser.try_get_elements(1, vec!["something".to_string()]) {
+		Err(Error::QuestionIndexOutOfRange { index, count }) => {
+			assert_eq!(index, 1);
+			assert_eq!(count, 1);
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+
+	let elements: Vec<ContentType> = parser.try_get_elements(0, vec!["something".to_string()]).expect("Question exists");
+	let content_ref = elements[0].clone().get_content().expect("Has content");
+	parser.try_register_change(Change::new(content_ref.clone(), "Foo".to_string())).expect("First change is fine");
+
+	// Overlap leaves the buffer as it was.
+	match parser.try_register_change(Change::new(content_ref.clone(), "Bar".to_string())) {
+		Err(Error::OverlappingChange(conflict)) => {
+			// The conflict tells which changes collided.
+			assert_eq!(conflict.existing.new_content, "Foo".to_string());
+			assert_eq!(conflict.incoming.new_content, "Bar".to_string());
+			assert_eq!(conflict.existing.position.range(), conflict.incoming.position.range());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+
+	parser.execute_changes();
+	assert!(parser.get_current_content().contains("<something>Foo</something>"));
+
+	// And the reference is now stale.
+	match parser.try_register_change(Change::new(content_ref, "Bar".to_string())) {
+		Err(Error::StaleReference { reference_version, current_version }) => {
+			assert_eq!(reference_version, 0);
+			assert_eq!(current_version, 1);
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+}
+
+/// Parse errors carry the position of the problem.
+#[test]
+fn parse_errors_have_positions() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+  	<pair></missing>
+  </question>
+</quiz>
+".to_string();
+	match QParser::try_from_string(data) {
+		Err(Error::XmlParse(e)) => {
+			assert_eq!(e.pos().row, 4);
+		},
+		_ => {
+			panic!("It was supposed to fail.");
+		}
+	}
+
+	// Missing files are errors not panics.
+	match QParser::try_load_xml_file("tests/tests/no-such-file.xml".to_string()) {
+		Err(Error::Io(e)) => {
+			assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+		},
+		_ => {
+			panic!("It was supposed to fail.");
+		}
+	}
+}
+
+/// Sometimes the same change gets produced twice, or a later change should
+/// simply override an earlier one. Policies allow dealing with that.
+#[test]
+fn conflict_policies() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+  	<name><text>Test</text></name>
+  	<something>Some</something>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["something".to_string()]);
+	let whole_element_ref = match &elements[0] {
+		ContentType::Element(_, whole_element_ref, _) => whole_element_ref.clone(),
+		_ => {
+			panic!("Wrong type found!");
+		}
+	};
+	let content_ref = elements[0].clone().get_content().expect("Has content");
+
+	// Identical changes merge.
+	parser.try_register_change_with_policy(Change::new(content_ref.clone(), "Foo".to_string()), ConflictPolicy::MergeIdentical).expect("No conflict");
+	parser.try_register_change_with_policy(Change::new(content_ref.clone(), "Foo".to_string()), ConflictPolicy::MergeIdentical).expect("Identical");
+	// But different ones still conflict.
+	match parser.try_register_change_with_policy(Change::new(content_ref.clone(), "Bar".to_string()), ConflictPolicy::MergeIdentical) {
+		Err(Error::OverlappingChange(conflict)) => {
+			assert_eq!(conflict.existing.new_content, "Foo".to_string());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+
+	// The last one can also just win, this replaces the change to the content.
+	parser.try_register_change_with_policy(Change::new(whole_element_ref, "<other/>".to_string()), ConflictPolicy::LastWins).expect("Replaces");
+	parser.execute_changes();
+	assert!(parser.get_current_content().contains("\t<other/>\n"));
+}
+
+/// Empty content is a single point, but two changes replacing it still collide
+/// like changes to any other content would.
+#[test]
+fn conflicting_changes_to_empty_content() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+  	<name><text>Test</text></name>
+  	<idnumber></idnumber>
+  </question>
+</quiz>
+".to_string();
+	let empty_content = |parser: &mut QParser| parser.get_elements(0, vec!["idnumber".to_string()])[0].clone().get_content().expect("Has content");
+
+	for policy in [ConflictPolicy::Reject, ConflictPolicy::MergeIdentical, ConflictPolicy::ComposeNested] {
+		let mut parser = QParser::from_string(data.clone()).expect("Valid input should not fail");
+		let content_ref = empty_content(&mut parser);
+		parser.try_register_change_with_policy(Change::new(content_ref.clone(), "A".to_string()), policy).expect("No conflict");
+		match parser.try_register_change_with_policy(Change::new(content_ref.clone(), "B".to_string()), policy) {
+			Err(Error::OverlappingChange(conflict)) => {
+				assert_eq!(conflict.existing.new_content, "A".to_string());
+				assert_eq!(conflict.incoming.new_content, "B".to_string());
+			},
+			_ => {
+				panic!("Wrong result with {policy:?}!");
+			}
+		}
+		parser.execute_changes();
+		assert!(parser.get_current_content().contains("<idnumber>A</idnumber>"));
+	}
+
+	// The last one wins, and still conflicts with the next one.
+	let mut parser = QParser::from_string(data.clone()).expect("Valid input should not fail");
+	let content_ref = empty_content(&mut parser);
+	parser.try_register_change_with_policy(Change::new(content_ref.clone(), "A".to_string()), ConflictPolicy::LastWins).expect("No conflict");
+	parser.try_register_change_with_policy(Change::new(content_ref.clone(), "B".to_string()), ConflictPolicy::LastWins).expect("Replaces");
+	match parser.try_register_change(Change::new(content_ref.clone(), "C".to_string())) {
+		Err(Error::OverlappingChange(conflict)) => {
+			assert_eq!(conflict.existing.new_content, "B".to_string());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	parser.execute_changes();
+	assert!(parser.get_current_content().contains("<idnumber>B</idnumber>"));
+}
+
+/// Whether an empty reference overlaps depends on where it is. At the start
+/// or end of another reference it only orders the changes, strictly inside
+/// it conflicts. Non-empty references touching each other never overlapped.
+#[test]
+fn edges_of_empty_references() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+  	<name><text>Test</text></name>
+  	<idnumber></idnumber>
+  	<other>x</other><more/>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let question_ref = parser.find_questions()[0].whole_element.clone();
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["idnumber".to_string(), "other".to_string(), "more".to_string()]);
+	let whole_refs: Vec<ContentRef> = elements.iter().map(|e| match e {
+		ContentType::Element(_, whole_element_ref, _) => whole_element_ref.clone(),
+		_ => {
+			panic!("Wrong type found!");
+		}
+	}).collect();
+	let empty_content = elements[0].clone().get_content().expect("Has content");
+
+	// Touching non-empty references.
+	parser.try_register_change(Change::new(whole_refs[1].clone(), "<other>y</other>".to_string())).expect("No conflict");
+	parser.try_register_change(Change::new(whole_refs[2].clone(), "<most/>".to_string())).expect("No conflict");
+	// Insertions at the start and end of a replaced element.
+	parser.try_register_change(Change::new(whole_refs[0].clone(), "<idnumber>1</idnumber>".to_string())).expect("No conflict");
+	parser.insert_before(&elements[0], "<a/>".to_string()).expect("Valid target");
+	parser.insert_after(&elements[0], "<b/>".to_string()).expect("Valid target");
+	// Empty content strictly inside the replaced element.
+	match parser.try_register_change(Change::new(empty_content, "2".to_string())) {
+		Err(Error::OverlappingChange(conflict)) => {
+			assert_eq!(conflict.existing.new_content, "<idnumber>1</idnumber>".to_string());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	// An insertion strictly inside a replaced question.
+	parser.try_register_change(Change::new(question_ref, "<question/>".to_string())).expect_err("Contains the others");
+
+	parser.try_execute_changes().expect("Still valid");
+	assert!(parser.get_current_content().contains("\t<a/>\n  \t<idnumber>1</idnumber>\n  \t<b/>\n  \t<other>y</other><most/>\n"));
+}
+
+/// `cdata_wrapped_version()` matches Moodle, and like Moodle it breaks the
+/// document if the value contains `]]>`, which STACK CASText can easily do.
+/// `strict_cdata_wrapped_version()` splits the CDATA section instead and
+/// `unwrap_cdata()` reads such split sections back.
+#[test]
+fn cdata_end_marker_in_values() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"stack\">
+    <name><text>CDATA</text></name>
+    <questiontext format=\"html\"><text><![CDATA[<p>Old</p>]]></text></questiontext>
+    <generalfeedback format=\"html\"><text>Old</text></generalfeedback>
+  </question>
+</quiz>
+".to_string();
+	let value: String = "<p>{@if a[[1]]>0 then 1@}]]]></p>".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let texts: Vec<ContentType> = parser.get_elements(0, vec!["text".to_string()]);
+	let questiontext = texts[1].clone().get_content().expect("Has content");
+	let generalfeedback = texts[2].clone().get_content().expect("Has content");
+
+	parser.register_change(Change::cdata_wrapped_version(questiontext.clone(), value.clone()));
+	match parser.try_execute_changes() {
+		Err(Error::BrokenChanges { .. }) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	parser.discard_changes();
+
+	parser.register_change(Change::strict_cdata_wrapped_version(questiontext, value.clone()));
+	parser.register_change(Change::style_preserving_version(generalfeedback, value.clone()));
+	parser.try_execute_changes().expect("Still valid");
+	let texts: Vec<ContentType> = parser.get_elements(0, vec!["text".to_string()]);
+	for text in &texts[1..] {
+		let content = text.clone().get_content().expect("Has content");
+		assert_eq!(content.content, "<![CDATA[<p>{@if a[[1]]]]><![CDATA[>0 then 1@}]]]]]><![CDATA[></p>]]>".to_string());
+		assert_eq!(content.unwrap_cdata(), value);
+		assert_eq!(content.decode(), value);
+	}
+}
+
+/// `unwrap_cdata()` is a small state machine, these are the cases where
+/// partial markers could confuse it.
+#[test]
+fn unwrapping_cdata_edge_cases() {
+	let cases = [
+		("plain ]] text", "plain ]] text"),
+		("<![CDATA[a]]>", "a"),
+		("<![CDATA[]]>", ""),
+		("<![CDATA[a]]><![CDATA[b]]>", "ab"),
+		("<![CDATA[a]]]]><![CDATA[>b]]>", "a]]>b"),
+		("<![CDATA[a]]]]]><![CDATA[>b]]>", "a]]]>b"),
+		("<![CDATA[]]]]><![CDATA[>]]>", "]]>"),
+		("<![CDATA[<![CDATA[]]>", "<![CDATA["),
+		("<b>x</b><![CDATA[<i>]]>", "<b>x</b><i>"),
+		("<b/><![CDATA[<]]><![CDATA[!]]>", "<b/><!"),
+		("<![CDATA[x]>]]>", "x]>"),
+		("<![CDATA[x] ]>]]>", "x] ]>")
+	];
+	for (raw, expected) in cases {
+		let data = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>x</text></name>
+    <foo>{raw}</foo>
+  </question>
+</quiz>
+");
+		let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+		let elements: Vec<ContentType> = parser.get_elements(0, vec!["foo".to_string()]);
+		let content = elements[0].clone().get_content().expect("Has content");
+		assert_eq!(content.content, raw.to_string());
+		assert_eq!(content.unwrap_cdata(), expected.to_string(), "Unwrapping {raw}");
+	}
+}
+
+/// A text-field whose `<text>`-element is an empty-tag has no content to point at,
+/// describing it is an error instead of a panic.
+#[test]
+fn text_field_without_text_content() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>x</text></name>
+    <questiontext format=\"html\"><text/></questiontext>
+    <generalfeedback format=\"html\"><text></text></generalfeedback>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	match parser.try_get_elements(0, vec!["questiontext".to_string()]) {
+		Err(Error::UnexpectedElement(name)) => {
+			assert_eq!(name, "text".to_string());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	match parser.select(0, "questiontext") {
+		Err(Error::UnexpectedElement(name)) => {
+			assert_eq!(name, "text".to_string());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+
+	// An empty text-element with an end-tag is fine.
+	let elements: Vec<ContentType> = parser.try_get_elements(0, vec!["generalfeedback".to_string()]).expect("Has a place for content");
+	let content = elements[0].clone().get_content().expect("Has content");
+	assert_eq!(content.content, "".to_string());
+}
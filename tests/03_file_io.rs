@@ -89,11 +89,11 @@ fn trying_to_save_a_broken_document() {
 
 			// For testing, lets replace that whoel element with something broken.
 			let change = Change::new(whole_element_ref.clone(), "<missing></part>".to_string());
-			// The system will happily execute that change.
+			// The system will not execute that change.
 			parser.register_change(change);
 			parser.execute_changes();
 
-			// The document is now broken, it should not be possible to save it to a file.
+			// The changes would break the document, it should not be possible to save it to a file.
 			let test_file = NamedTempFile::new("test.xml").unwrap();
 			let file_name: String = test_file.to_str().expect("Some sort of name").to_string();
 			let _ = std::fs::write(file_name.clone(), "test".to_string());
@@ -116,3 +116,212 @@ fn trying_to_save_a_broken_document() {
 		}
 	}
 }
+
+/// Any `io::Read` and `io::Write` will do as well, for pipelines and
+/// in-memory buffers.
+#[test]
+fn reading_and_writing_streams() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+  	<name><text>...</text></name>
+  	<trouble/>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+  	<name><text>Named</text></name>
+  	<trouble/>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_reader(data.as_bytes()).expect("Valid input should not fail");
+	let questions: Vec<Question> = parser.find_questions();
+	parser.register_change(Change::new(questions[0].name.clone(), "Named".to_string()));
+
+	let mut output: Vec<u8> = Vec::new();
+	parser.write_to(&mut output).expect("Valid content");
+	assert_eq!(String::from_utf8(output).expect("UTF-8"), target_data);
+	assert_eq!(parser.content(), target_data);
+
+	// Broken changes do not get written.
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["trouble".to_string()]);
+	if let ContentType::Element(_, whole_element_ref, _) = &elements[0] {
+		parser.register_change(Change::new(whole_element_ref.clone(), "<missing></part>".to_string()));
+	}
+	let mut output: Vec<u8> = Vec::new();
+	match parser.write_to(&mut output) {
+		Err(Error::BrokenChanges { .. }) => {
+			assert!(output.is_empty());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+
+	// Nor does something that is not UTF-8 get read.
+	match QParser::from_reader(&[0x3c, 0xff, 0x3e][..]) {
+		Err(Error::Io(_)) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+}
+
+/// Saving replaces the file in one go, can leave a backup of the old
+/// content and does not touch files that would not change.
+#[test]
+fn saving_with_options() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+  	<name><text>...</text></name>
+  </question>
+</quiz>
+".to_string();
+	let dir = assert_fs::TempDir::new().unwrap();
+	let file_name = dir.path().join("quiz.xml");
+	std::fs::write(&file_name, data.clone()).expect("Writable");
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		std::fs::set_permissions(&file_name, std::fs::Permissions::from_mode(0o640)).expect("Can set");
+	}
+	let mut parser = QParser::load_xml_file(file_name.to_str().expect("Some sort of name").to_string()).expect("Valid input should not fail");
+
+	// Nothing changed, nothing written.
+	let modified = std::fs::metadata(&file_name).expect("Exists").modified().expect("Has a time");
+	std::thread::sleep(std::time::Duration::from_millis(20));
+	let outcome = parser.try_save_to_file_with_options(&file_name, SaveOptions { backup: true, skip_unchanged: true }).expect("Saves");
+	assert_eq!(outcome, SaveOutcome::Unchanged);
+	assert_eq!(std::fs::metadata(&file_name).expect("Exists").modified().expect("Has a time"), modified);
+	assert!(!dir.path().join("quiz.xml.bak").exists());
+
+	// Changes get written, with a backup of the old.
+	let questions: Vec<Question> = parser.find_questions();
+	parser.register_change(Change::new(questions[0].name.clone(), "Named".to_string()));
+	let outcome = parser.try_save_to_file_with_options(&file_name, SaveOptions { backup: true, skip_unchanged: true }).expect("Saves");
+	assert_eq!(outcome, SaveOutcome::Written);
+	assert_eq!(std::fs::read_to_string(&file_name).expect("Readable"), parser.get_current_content());
+	assert_eq!(std::fs::read_to_string(dir.path().join("quiz.xml.bak")).expect("Readable"), data);
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		assert_eq!(std::fs::metadata(&file_name).expect("Exists").permissions().mode() & 0o777, 0o640);
+	}
+
+	// And no temporary files are left behind.
+	let mut names: Vec<String> = std::fs::read_dir(dir.path()).expect("Readable").map(|e| e.expect("Entry").file_name().to_string_lossy().to_string()).collect();
+	names.sort();
+	assert_eq!(names, vec!["quiz.xml".to_string(), "quiz.xml.bak".to_string()]);
+
+	// Unless asked not to skip, identical content gets written as well.
+	let outcome = parser.try_save_to_file_with_options(&file_name, SaveOptions { backup: false, skip_unchanged: false }).expect("Saves");
+	assert_eq!(outcome, SaveOutcome::Written);
+
+	// Which is also what the other ways of saving do.
+	let old_time = std::time::SystemTime::UNIX_EPOCH;
+	std::fs::File::options().write(true).open(&file_name).expect("Writable").set_modified(old_time).expect("Settable");
+	parser.try_save_to_file(&file_name).expect("Saves");
+	assert!(std::fs::metadata(&file_name).expect("Exists").modified().expect("Has a time") > old_time);
+}
+
+/// Documents in ISO-8859-1 and Windows-1252 are read and written in their
+/// own encoding, while everything in between is UTF-8. Characters the
+/// encoding cannot represent cannot be written.
+#[test]
+fn other_encodings() {
+	let start: &[u8] = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>
+<quiz>
+  <question type=\"some\">
+  	<name><text>M";
+	let end: &[u8] = b"</text></name>
+  </question>
+</quiz>
+";
+	let data: Vec<u8> = [start, &[0xE4, b'r', b'k'], end].concat();
+	let mut parser = QParser::from_reader(&data[..]).expect("Valid input should not fail");
+	assert_eq!(parser.encoding(), Encoding::Latin1);
+	let questions: Vec<Question> = parser.find_questions();
+	assert_eq!(questions[0].name.content, "Märk".to_string());
+
+	// Euro did not exist back then.
+	parser.register_change(Change::new(questions[0].name.clone(), "5 €".to_string()));
+	let mut output: Vec<u8> = Vec::new();
+	match parser.write_to(&mut output) {
+		Err(Error::Unencodable { character, encoding, .. }) => {
+			assert_eq!(character, '€');
+			assert_eq!(encoding, Encoding::Latin1);
+			assert!(output.is_empty());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	let test_file = NamedTempFile::new("latin.xml").unwrap();
+	match parser.try_save_to_file_with_options(test_file.path(), SaveOptions::default()) {
+		Err(Error::Unencodable { .. }) => {
+			assert!(!test_file.path().exists());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	// Nothing was executed, the change still waits in the buffer.
+	assert!(parser.content().contains("Märk"));
+	assert_eq!(parser.discard_changes().len(), 1);
+	parser.register_change(Change::new(questions[0].name.clone(), "Müller".to_string()));
+	let mut output: Vec<u8> = Vec::new();
+	parser.write_to(&mut output).expect("Encodable");
+	assert_eq!(output, [start, &[0xFC, b'l', b'l', b'e', b'r'], end].concat());
+
+	// Windows-1252 has it, and round trips as it was.
+	let mut data: Vec<u8> = b"<?xml version='1.0' encoding='windows-1252'?>
+<quiz><question type=\"some\"><name><text>".to_vec();
+	data.extend_from_slice(&[0x80, 0x93, b'x', 0x94, 0x81]);
+	data.extend_from_slice(b"</text></name></question></quiz>");
+	let mut parser = QParser::from_reader(&data[..]).expect("Valid input should not fail");
+	assert_eq!(parser.encoding(), Encoding::Windows1252);
+	assert_eq!(parser.find_questions()[0].name.content, "€\u{201C}x\u{201D}\u{81}".to_string());
+	let mut output: Vec<u8> = Vec::new();
+	parser.write_to(&mut output).expect("Encodable");
+	assert_eq!(output, data);
+
+	// Not everything is supported.
+	match QParser::from_reader(&b"<?xml version=\"1.0\" encoding=\"UTF-16\"?><quiz/>"[..]) {
+		Err(Error::UnsupportedEncoding(name)) => {
+			assert_eq!(name, "UTF-16".to_string());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+}
+
+/// A byte order mark is kept as it was, but is not part of the content.
+#[test]
+fn byte_order_mark() {
+	let data: Vec<u8> = b"\xEF\xBB\xBF<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\"><name><text>...</text></name></question>
+</quiz>
+".to_vec();
+	let dir = assert_fs::TempDir::new().unwrap();
+	let file_name = dir.path().join("quiz.xml");
+	std::fs::write(&file_name, &data).expect("Writable");
+
+	let mut parser = QParser::try_load_xml_file(&file_name).expect("Valid input should not fail");
+	assert!(parser.has_bom());
+	assert!(parser.content().starts_with("<?xml"));
+	let outcome = parser.try_save_to_file_with_options(&file_name, SaveOptions { backup: false, skip_unchanged: true }).expect("Saves");
+	assert_eq!(outcome, SaveOutcome::Unchanged);
+
+	let questions: Vec<Question> = parser.find_questions();
+	parser.register_change(Change::new(questions[0].name.clone(), "Named".to_string()));
+	parser.try_save_to_file(&file_name).expect("Saves");
+	let written: Vec<u8> = std::fs::read(&file_name).expect("Readable");
+	assert!(written.starts_with(b"\xEF\xBB\xBF<?xml"));
+	assert_eq!(&written[3..], parser.content().as_bytes());
+}
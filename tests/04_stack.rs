@@ -71,3 +71,86 @@ fn access_by_type() {
 	assert_eq!(all_keyval_catenated, "a: 1+rand(5);\r\nb: 2+rand(5);\r\nta: a+b;||");
 }
 
+
+#[test]
+fn stack_errors() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\"><name><text>test1</text></name></question>
+  <question type=\"stack\"><name><text>test2</text></name></question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+
+	// Asking for the wrong type of question.
+	match parser.try_get_as_stack_question(0) {
+		Err(Error::WrongQuestionType { index, expected, found }) => {
+			assert_eq!(index, 0);
+			assert_eq!(expected, "stack".to_string());
+			assert_eq!(found, "some".to_string());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+
+	// Or a question that is missing required parts.
+	match parser.try_get_as_stack_question(1) {
+		Err(Error::MissingElement(name)) => {
+			assert_eq!(name, "questiontext".to_string());
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+}
+
+/// The variable fields may be present but without their text, those are reported as
+/// missing rather than taking the whole run down.
+#[test]
+fn stack_variables_without_text() {
+	let original: String = std::fs::read_to_string("tests/tests/minimal-stack.xml").expect("Test file exists");
+	let cases = [
+		("questionvariables", "<questionvariables/>", "text"),
+		("questionvariables", "<questionvariables><text/></questionvariables>", "questionvariables"),
+		("feedbackvariables", "<feedbackvariables/>", "text"),
+		("feedbackvariables", "<feedbackvariables><text/></feedbackvariables>", "feedbackvariables")
+	];
+	for (element, replacement, missing) in cases {
+		let start: usize = original.find(&format!("<{element}>")).expect("Element exists");
+		let end: usize = original.find(&format!("</{element}>")).expect("Element exists") + element.len() + 3;
+		let data: String = format!("{}{replacement}{}", &original[..start], &original[end..]);
+		let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+		match parser.try_get_as_stack_question(0) {
+			Err(Error::MissingElement(name)) => {
+				assert_eq!(name, missing.to_string(), "With {replacement}");
+			},
+			_ => {
+				panic!("Wrong result!");
+			}
+		}
+	}
+}
+
+/// Replacement content written in Rust tends to use `\n`, to avoid mixed
+/// line-breaks the content can be normalised to match what it replaces.
+#[test]
+fn matching_line_endings() {
+	let mut parser = QParser::load_xml_file("tests/tests/minimal-stack.xml".to_string()).expect("Valid input should not fail");
+	let question = parser.get_as_stack_question(0);
+	assert_eq!(LineEnding::detect(&question.questionvariables.content), Some(LineEnding::CrLf));
+
+	let change: Change = Change::line_ending_normalized_version(question.questionvariables.clone(), "a: 1+rand(5);\nb: 3;\nta: a+b;".to_string(), parser.line_ending());
+	assert_eq!(change.new_content, "a: 1+rand(5);\r\nb: 3;\r\nta: a+b;".to_string());
+	parser.register_change(change);
+	let question = parser.get_as_stack_question(0);
+	assert_eq!(question.questionvariables.content, "a: 1+rand(5);\r\nb: 3;\r\nta: a+b;".to_string());
+
+	// Without line-breaks in the replaced content the default is used.
+	let change: Change = Change::line_ending_normalized_version(question.inputs.get("ans1").unwrap().tans.clone(), "x\r\ny".to_string(), LineEnding::Lf);
+	assert_eq!(change.new_content, "x\ny".to_string());
+
+	// And the raw version does nothing.
+	let change: Change = Change::new(question.questionvariables.clone(), "a: 1;\nb: 2;".to_string());
+	assert_eq!(change.new_content, "a: 1;\nb: 2;".to_string());
+}
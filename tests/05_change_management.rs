@@ -0,0 +1,378 @@
+use position_preserving_moodle_question_xml_edit::*;
+
+/// Normally one cannot change a whole and its parts in the same batch of changes,
+/// but if registered with the `ComposeNested` policy the inner changes get
+/// applied to the replacement of the outer one.
+#[test]
+fn composing_nested_changes() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <questiontext format=\"html\">
+      <text><![CDATA[<p>Old text <img src=\"@@PLUGINFILE@@/a.png\"></p>]]></text>
+      <file name=\"a.png\" path=\"/\" encoding=\"base64\">AAAA</file>
+      <file name=\"b.png\" path=\"/\" encoding=\"base64\">BBBB</file>
+    </questiontext>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <questiontext format=\"html\">
+      <text><![CDATA[<p>New text <img src=\"@@PLUGINFILE@@/a.png\"></p>]]></text>
+      <file name=\"a.png\" path=\"/\" encoding=\"base64\">AAAA</file>
+      
+    </questiontext>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let questions: Vec<Question> = parser.find_questions();
+	let files: Vec<ContentType> = parser.get_elements(0, vec!["file".to_string()]);
+
+	// One part of a script rewrites the whole question.
+	let whole = questions[0].whole_element.clone();
+	let rewritten = whole.content.replace("Old text", "New text");
+	parser.try_register_change_with_policy(Change::new(whole, rewritten), ConflictPolicy::ComposeNested).expect("No conflicts");
+
+	// While another drops an attachment inside it.
+	if let ContentType::Element(_, file_ref, _) = &files[1] {
+		parser.try_register_change_with_policy(Change::new(file_ref.clone(), "".to_string()), ConflictPolicy::ComposeNested).expect("Composes");
+	} else {
+		panic!("Wrong type found!");
+	}
+
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.get_current_content(), target_data);
+}
+
+/// If the outer change has replaced the region the inner change targets
+/// composition is not possible.
+#[test]
+fn composing_nested_changes_into_changed_region() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <something>Some</something>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["something".to_string()]);
+	let content_ref = elements[0].clone().get_content().expect("Has content");
+	if let ContentType::Element(_, whole_element_ref, _) = &elements[0] {
+		// Order does not matter, inner first this time.
+		parser.try_register_change_with_policy(Change::new(content_ref, "Foo".to_string()), ConflictPolicy::ComposeNested).expect("No conflicts");
+		match parser.try_register_change_with_policy(Change::new(whole_element_ref.clone(), "<nimi>Bar</nimi>".to_string()), ConflictPolicy::ComposeNested) {
+			Err(Error::UncomposableChange(conflict)) => {
+				assert_eq!(conflict.existing.new_content, "Foo".to_string());
+				assert_eq!(conflict.incoming.new_content, "<nimi>Bar</nimi>".to_string());
+			},
+			_ => {
+				panic!("Wrong result!");
+			}
+		}
+
+		// The buffer still has the first change.
+		parser.try_execute_changes().expect("Still valid");
+		assert!(parser.get_current_content().contains("<something>Foo</something>"));
+	} else {
+		panic!("Wrong type found!");
+	}
+}
+
+/// After changes have been executed the references from earlier searches
+/// can still be used as long as the change did not touch them. They get rebased
+/// to match the new positions in the document.
+#[test]
+fn rebasing_references() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <first>Some</first>
+    <second>Thing</second>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <first>Lorem ipsum</first>
+    <second>dolor</second>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["first".to_string(), "second".to_string()]);
+	let first = elements[0].clone().get_content().expect("Has content");
+	let second = elements[1].clone().get_content().expect("Has content");
+
+	parser.register_change(Change::new(first.clone(), "Lorem ipsum".to_string()));
+	parser.try_execute_changes().expect("Still valid");
+
+	// The second one moved, but is still the same.
+	let rebased = parser.rebase(&second).expect("Not touched");
+	assert_eq!(rebased.content, "Thing".to_string());
+	assert_eq!(rebased.range().start, second.range().start + "Lorem ipsum".len() - "Some".len());
+
+	// Registering changes does that automatically.
+	parser.register_change(Change::new(second, "dolor".to_string()));
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.get_current_content(), target_data);
+
+	// But the first one was touched, so it cannot be rebased.
+	match parser.rebase(&first) {
+		Err(Error::StaleReference { reference_version, current_version }) => {
+			assert_eq!(reference_version, 0);
+			assert_eq!(current_version, 2);
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+}
+
+/// Changes that would break the document are not executed, the error tells
+/// which of the changes caused it and the document stays usable.
+#[test]
+fn rolling_back_broken_changes() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <first>Some</first>
+    <second>Thing</second>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data.clone()).expect("Valid input should not fail");
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["first".to_string(), "second".to_string()]);
+	let first = elements[0].clone().get_content().expect("Has content");
+	let second = elements[1].clone().get_content().expect("Has content");
+
+	parser.register_change(Change::new(first.clone(), "Fine".to_string()));
+	parser.register_change(Change::new(second.clone(), "<broken>".to_string()));
+	match parser.try_execute_changes() {
+		Err(Error::BrokenChanges { offending, .. }) => {
+			assert_eq!(offending, vec![Change::new(second.clone(), "<broken>".to_string())]);
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+
+	// Nothing happened, and searches keep failing until the changes are dealt with.
+	assert_eq!(parser.get_current_content(), data);
+	assert!(parser.try_find_questions().is_err());
+	let pending: Vec<Change> = parser.discard_changes();
+	assert_eq!(pending.len(), 2);
+	parser.register_change(pending[0].clone());
+	parser.try_execute_changes().expect("Still valid");
+	assert!(parser.get_current_content().contains("<first>Fine</first>"));
+	assert_eq!(parser.find_questions().len(), 1);
+
+	// And the old references still work where nothing was changed.
+	parser.register_change(Change::new(second, "Other".to_string()));
+	parser.try_execute_changes().expect("Still valid");
+	assert!(parser.get_current_content().contains("<second>Other</second>"));
+}
+
+/// Multiple batches can be grouped into a transaction and rolled back
+/// as a whole. References from during the transaction become stale.
+#[test]
+fn transactions() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <first>Some</first>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data.clone()).expect("Valid input should not fail");
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["first".to_string()]);
+	let before = elements[0].clone().get_content().expect("Has content");
+
+	parser.begin_transaction().expect("None open");
+	assert!(parser.begin_transaction().is_err());
+	parser.register_change(Change::new(before.clone(), "One".to_string()));
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["first".to_string()]);
+	let during = elements[0].clone().get_content().expect("Has content");
+	parser.register_change(Change::new(during.clone(), "Two".to_string()));
+	parser.try_execute_changes().expect("Still valid");
+	assert!(parser.get_current_content().contains("<first>Two</first>"));
+
+	parser.rollback_transaction().expect("Open");
+	assert_eq!(parser.get_current_content(), data);
+	assert!(parser.rollback_transaction().is_err());
+	match parser.try_register_change(Change::new(during, "Three".to_string())) {
+		Err(Error::StaleReference { .. }) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+
+	// Committed ones stay.
+	parser.begin_transaction().expect("None open");
+	parser.register_change(Change::new(before, "Four".to_string()));
+	parser.commit_transaction().expect("Valid changes");
+	assert!(parser.commit_transaction().is_err());
+	assert!(parser.get_current_content().contains("<first>Four</first>"));
+}
+
+/// Executed batches can be undone and redone, the version moves back
+/// and forth with them so that references stay valid where they should.
+#[test]
+fn undo_and_redo() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <first>Some</first>
+    <second>Thing</second>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data.clone()).expect("Valid input should not fail");
+	assert!(!parser.undo().expect("Nothing pending"));
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["first".to_string(), "second".to_string()]);
+	let first = elements[0].clone().get_content().expect("Has content");
+	let second = elements[1].clone().get_content().expect("Has content");
+
+	parser.register_change(Change::new(first.clone(), "Lorem ipsum".to_string()));
+	parser.register_change(Change::new(second.clone(), "dolor".to_string()));
+	parser.try_execute_changes().expect("Still valid");
+	let changed: String = parser.get_current_content();
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["first".to_string()]);
+	let changed_first = elements[0].clone().get_content().expect("Has content");
+
+	// Undo needs an empty buffer.
+	parser.register_change(Change::new(changed_first.clone(), "sit".to_string()));
+	match parser.undo() {
+		Err(Error::PendingChanges) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	parser.discard_changes();
+
+	assert!(parser.undo().expect("Nothing pending"));
+	assert_eq!(parser.get_current_content(), data);
+	assert!(!parser.undo().expect("Nothing pending"));
+	// The original references work again, the newer ones do not.
+	assert_eq!(parser.rebase(&first).expect("Current").content, "Some".to_string());
+	assert!(parser.rebase(&changed_first).is_err());
+
+	assert!(parser.redo().expect("Nothing pending"));
+	assert_eq!(parser.get_current_content(), changed);
+	assert!(!parser.redo().expect("Nothing pending"));
+	assert_eq!(parser.rebase(&changed_first).expect("Current").content, "Lorem ipsum".to_string());
+
+	// New changes after an undo mean there is nothing to redo.
+	parser.undo().expect("Nothing pending");
+	parser.register_change(Change::new(second, "amet".to_string()));
+	parser.try_execute_changes().expect("Still valid");
+	assert!(!parser.redo().expect("Nothing pending"));
+	assert!(parser.get_current_content().contains("<first>Some</first>"));
+	assert!(parser.get_current_content().contains("<second>amet</second>"));
+
+	// And the history is limited.
+	parser.set_history_limit(0);
+	assert!(!parser.undo().expect("Nothing pending"));
+}
+
+/// Limiting the history after undoing keeps the batches that can be redone next.
+#[test]
+fn history_limit_after_undo() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <first>Some</first>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data.clone()).expect("Valid input should not fail");
+	let first = parser.get_elements(0, vec!["first".to_string()])[0].clone().get_content().expect("Has content");
+	parser.register_change(Change::new(first, "A".to_string()));
+	parser.try_execute_changes().expect("Still valid");
+	let after_a: String = parser.get_current_content();
+	let first = parser.get_elements(0, vec!["first".to_string()])[0].clone().get_content().expect("Has content");
+	parser.register_change(Change::new(first, "B".to_string()));
+	parser.try_execute_changes().expect("Still valid");
+
+	assert!(parser.undo().expect("Nothing pending"));
+	assert!(parser.undo().expect("Nothing pending"));
+	assert_eq!(parser.get_current_content(), data);
+
+	parser.set_history_limit(1);
+	assert!(parser.redo().expect("Nothing pending"));
+	assert_eq!(parser.get_current_content(), after_a);
+	assert!(!parser.redo().expect("Nothing pending"));
+}
+
+/// Changes are executed in document order. Insertions to the same position
+/// end up in registration order and before a replacement starting there.
+#[test]
+fn execution_order_of_touching_changes() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <first>Some</first>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <a/>
+    <b/>
+    <replaced/>
+    <c/>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["first".to_string()]);
+	if let ContentType::Element(_, whole_element_ref, _) = &elements[0] {
+		parser.insert_after(&elements[0], "<c/>".to_string()).expect("Valid target");
+		parser.register_change(Change::new(whole_element_ref.clone(), "<replaced/>".to_string()));
+		parser.insert_before(&elements[0], "<a/>".to_string()).expect("Valid target");
+		parser.insert_before(&elements[0], "<b/>".to_string()).expect("Valid target");
+	} else {
+		panic!("Wrong type found!");
+	}
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.get_current_content(), target_data);
+}
+
+/// Several insertions to the very same position come out in the order they
+/// were registered, not in the reverse order like they did up to 0.1.2.
+#[test]
+fn execution_order_of_insertions_to_the_same_position() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <first format=\"html\"/>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["name".to_string(), "first".to_string()]);
+	for tag in ["a", "b", "c"] {
+		parser.insert_after(&elements[0], format!("<{tag}/>")).expect("Valid target");
+		parser.insert_attribute(&elements[1], tag, tag).expect("Valid target");
+	}
+	parser.try_execute_changes().expect("Still valid");
+	assert!(parser.get_current_content().contains("</name>\n    <a/>\n    <b/>\n    <c/>\n    <first"));
+	assert!(parser.get_current_content().contains("<first format=\"html\" a=\"a\" b=\"b\" c=\"c\"/>"));
+}
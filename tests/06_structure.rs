@@ -0,0 +1,373 @@
+use position_preserving_moodle_question_xml_edit::*;
+
+/// Besides replacing content one can add new elements, the tool tries to
+/// indent them like the surrounding elements are indented. Give the new
+/// content without any indentation and it will be indented to match.
+#[test]
+fn inserting_elements() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 1  -->
+  <question type=\"some\">
+    <name>
+      <text>Test question</text>
+    </name>
+    <hint format=\"html\">
+      <text>Second hint</text>
+    </hint>
+    <tags/>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 1  -->
+  <question type=\"some\">
+    <name>
+      <text>Test question</text>
+    </name>
+    <hint format=\"html\">
+      <text>First hint</text>
+    </hint>
+    <hint format=\"html\">
+      <text>Second hint</text>
+    </hint>
+    <hint format=\"html\">
+      <text>Third hint</text>
+    </hint>
+    <hint format=\"html\">
+      <text>Fourth hint</text>
+    </hint>
+    <tags>
+      <tag><text>foo</text></tag>
+      <tag><text>bar</text></tag>
+      <tag><text>baz</text></tag>
+    </tags>
+    <idnumber></idnumber>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	// Note that searches include the question element itself if asked for.
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["question".to_string(), "name".to_string(), "hint".to_string(), "tags".to_string()]);
+	let question = elements[0].clone();
+	let name = elements[1].clone();
+	let tags = elements[3].clone();
+
+	// New things on both sides of an element.
+	parser.insert_after(&name, "<hint format=\"html\">\n  <text>First hint</text>\n</hint>".to_string()).expect("Fine");
+	parser.insert_before(&tags, "<hint format=\"html\">\n  <text>Fourth hint</text>\n</hint>".to_string()).expect("Fine");
+	// The hint is a MoodleTextElement, those work as anchors as well.
+	parser.insert_after(&elements[2], "<hint format=\"html\">\n  <text>Third hint</text>\n</hint>".to_string()).expect("Fine");
+	// Children to an empty-tag, these end up in the order they were registered.
+	parser.insert_as_last_child(&tags, "<tag><text>foo</text></tag>".to_string()).expect("Fine");
+	parser.insert_as_last_child(&tags, "<tag><text>bar</text></tag>".to_string()).expect("Fine");
+	parser.try_execute_changes().expect("Still valid");
+	// The old references to the question and the tags cannot be used anymore as their 
+	// content has changed, but the new ones from a new search can.
+	match parser.insert_as_last_child(&question, "<idnumber></idnumber>".to_string()) {
+		Err(Error::StaleReference { .. }) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["question".to_string(), "tags".to_string()]);
+	parser.insert_as_last_child(&elements[1], "<tag><text>baz</text></tag>".to_string()).expect("Fine");
+	parser.insert_as_last_child(&elements[0], "<idnumber></idnumber>".to_string()).expect("Fine");
+
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.get_current_content(), target_data);
+}
+
+/// Elements without children get opened up once, however many children
+/// are added to them in the same batch.
+#[test]
+fn inserting_many_children_to_empty_elements() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <hints></hints>
+    <answers>
+    </answers>
+    <tags />
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <hints>
+      <hint>1</hint>
+      <hint>2</hint>
+    </hints>
+    <answers>
+      <answer>1</answer>
+      <answer>2</answer>
+    </answers>
+    <tags>
+      <tag>1</tag>
+      <tag>2</tag>
+    </tags>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["hints".to_string(), "answers".to_string(), "tags".to_string()]);
+	for n in 1..=2 {
+		parser.insert_as_last_child(&elements[0], format!("<hint>{n}</hint>")).expect("Fine");
+		parser.insert_as_last_child(&elements[1], format!("<answer>{n}</answer>")).expect("Fine");
+		parser.insert_as_last_child(&elements[2], format!("<tag>{n}</tag>")).expect("Fine");
+	}
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.get_current_content(), target_data);
+}
+
+/// Line-endings of the document are used for the new content.
+#[test]
+fn inserting_with_windows_line_endings() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n<quiz>\r\n\t<question type=\"some\">\r\n\t\t<name><text>Test</text></name>\r\n\t\t<tags>\r\n\t\t</tags>\r\n\t</question>\r\n</quiz>\r\n".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n<quiz>\r\n\t<question type=\"some\">\r\n\t\t<name><text>Test</text></name>\r\n\t\t<tags>\r\n\t\t\t<tag>\r\n\t\t\t\t<text>foo</text>\r\n\t\t\t</tag>\r\n\t\t</tags>\r\n\t</question>\r\n</quiz>\r\n".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let tags: Vec<ContentType> = parser.get_elements(0, vec!["tags".to_string()]);
+	parser.insert_as_last_child(&tags[0], "<tag>\n\t<text>foo</text>\n</tag>".to_string()).expect("Fine");
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.get_current_content(), target_data);
+}
+
+/// Documents put together from several exports may mix line-endings, new content
+/// follows the ones around the place it goes to.
+#[test]
+fn inserting_with_mixed_line_endings() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<quiz>\n  <question type=\"some\">\n    <name><text>First</text></name>\n    <tags/>\n  </question>\n  <question type=\"some\">\r\n    <name><text>Second</text></name>\r\n    <tags/>\r\n  </question>\n</quiz>\n".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<quiz>\n  <question type=\"some\">\n    <name><text>First</text></name>\n    <idnumber></idnumber>\n    <tags/>\n  </question>\n  <question type=\"some\">\r\n    <name><text>Second</text></name>\r\n    <idnumber></idnumber>\r\n    <tags>\r\n      <tag>\r\n        <text>foo</text>\r\n      </tag>\r\n    </tags>\r\n  </question>\n</quiz>\n".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let first: Vec<ContentType> = parser.get_elements(0, vec!["tags".to_string()]);
+	let second: Vec<ContentType> = parser.get_elements(1, vec!["tags".to_string()]);
+	parser.insert_before(&first[0], "<idnumber></idnumber>".to_string()).expect("Fine");
+	parser.insert_before(&second[0], "<idnumber></idnumber>".to_string()).expect("Fine");
+	parser.insert_as_last_child(&second[0], "<tag>\n  <text>foo</text>\n</tag>".to_string()).expect("Fine");
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.get_current_content(), target_data);
+}
+
+/// Replacing an element with an empty string leaves its line behind,
+/// removal of elements cleans up the line as well if the element was 
+/// alone on it.
+#[test]
+fn removing_elements() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 1  -->
+  <question type=\"some\">
+    <name><text>First</text></name>
+    <questiontext format=\"html\">
+      <text>Text</text>
+      <file name=\"a.png\" path=\"/\" encoding=\"base64\">AAAA</file>
+      <file name=\"b.png\" path=\"/\" encoding=\"base64\">BBBB</file>
+    </questiontext>
+  </question>
+<!-- question: 2  -->
+  <question type=\"some\">
+    <name><text>Second</text></name>
+  </question>
+<!-- question: 3  -->
+  <question type=\"some\">
+    <name><text>Third</text></name>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 1  -->
+  <question type=\"some\">
+    <name><text>First</text></name>
+    <questiontext format=\"html\">
+      <text>Text</text>
+      <file name=\"b.png\" path=\"/\" encoding=\"base64\">BBBB</file>
+    </questiontext>
+  </question>
+<!-- question: 2  -->
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let questions: Vec<Question> = parser.find_questions();
+	let files: Vec<ContentType> = parser.get_elements(0, vec!["file".to_string()]);
+
+	if let ContentType::Element(_, whole_element_ref, _) = &files[0] {
+		parser.remove_element(whole_element_ref).expect("Fine");
+	} else {
+		panic!("Wrong type found!");
+	}
+
+	// Questions can be removed with or without the comment before them.
+	parser.remove_question(&questions[1], false).expect("Fine");
+	parser.remove_question(&questions[2], true).expect("Fine");
+
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.get_current_content(), target_data);
+}
+
+/// Questions can be moved from one category to another, the lines of the
+/// question and its comment move as they are. New categories can be added
+/// and categories renamed, their subcategories follow. The paths are written
+/// like they were, plain ones follow the rules Moodle uses.
+#[test]
+fn reorganising_categories() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 0  -->
+  <question type=\"category\">
+    <category>
+      <text>$course$/top/A</text>
+    </category>
+  </question>
+<!-- question: 1  -->
+  <question type=\"some\">
+    <name><text>First</text></name>
+  </question>
+<!-- question: 2  -->
+  <question type=\"some\">
+    <name><text>Second</text></name>
+  </question>
+<!-- question: 0  -->
+  <question type=\"category\">
+    <category>
+      <text><![CDATA[$course$/top/A/B]]></text>
+    </category>
+  </question>
+<!-- question: 3  -->
+  <question type=\"some\">
+    <name><text>Third</text></name>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 0  -->
+  <question type=\"category\">
+    <category>
+      <text><![CDATA[$course$/top/C & D]]></text>
+    </category>
+  </question>
+<!-- question: 2  -->
+  <question type=\"some\">
+    <name><text>Second</text></name>
+  </question>
+<!-- question: 0  -->
+  <question type=\"category\">
+    <category>
+      <text><![CDATA[$course$/top/C & D/B]]></text>
+    </category>
+  </question>
+<!-- question: 3  -->
+  <question type=\"some\">
+    <name><text>Third</text></name>
+  </question>
+<!-- question: 0  -->
+  <question type=\"category\">
+    <category>
+      <text>$course$/top/New</text>
+    </category>
+    <info format=\"moodle_auto_format\">
+      <text></text>
+    </info>
+  </question>
+<!-- question: 1  -->
+  <question type=\"some\">
+    <name><text>First</text></name>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+
+	parser.add_category("$course$/top/New").expect("Valid document");
+	let categories: Vec<Category> = parser.find_categories().expect("Valid document");
+	assert_eq!(categories.len(), 3);
+	let questions: Vec<Question> = parser.find_questions();
+	parser.move_question_to_category(&questions[0], &categories[2]).expect("Valid move");
+	// Already there, nothing happens.
+	parser.move_question_to_category(&questions[2], &categories[1]).expect("Valid move");
+	assert_eq!(parser.rename_category("$course$/top/A", "$course$/top/C & D").expect("Valid document"), 2);
+	parser.try_execute_changes().expect("Valid changes");
+	assert_eq!(parser.get_current_content(), target_data);
+
+	let questions: Vec<Question> = parser.find_questions();
+	let in_categories: Vec<Option<usize>> = questions.iter().map(|q| q.category).collect();
+	assert_eq!(in_categories, vec![Some(0), Some(1), Some(2)]);
+	assert_eq!(parser.rename_category("$course$/top/Missing", "$course$/top/X").expect("Valid document"), 0);
+}
+
+/// New categories and their comments are indented like the questions and comments
+/// around them, and renaming does not execute the changes registered before it.
+#[test]
+fn categories_in_tab_indented_documents() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<quiz>\n\t<question type=\"category\">\n\t\t<category>\n\t\t\t<text>$course$/top/A</text>\n\t\t</category>\n\t</question>\n\t<!-- question: 1  -->\n\t<question type=\"some\">\n\t\t<name><text>First</text></name>\n\t</question>\n</quiz>\n".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<quiz>\n\t<question type=\"category\">\n\t\t<category>\n\t\t\t<text>$course$/top/B</text>\n\t\t</category>\n\t</question>\n\t<!-- question: 1  -->\n\t<question type=\"some\">\n\t\t<name><text>Renamed</text></name>\n\t</question>\n\t<!-- question: 0  -->\n\t<question type=\"category\">\n\t\t<category>\n\t\t\t<text>$course$/top/New</text>\n\t\t</category>\n\t\t<info format=\"moodle_auto_format\">\n\t\t\t<text></text>\n\t\t</info>\n\t</question>\n</quiz>\n".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let questions: Vec<Question> = parser.find_questions();
+	parser.register_change(Change::new(questions[0].name.clone(), "Renamed".to_string()));
+	parser.add_category("$course$/top/New").expect("Valid document");
+	assert_eq!(parser.rename_category("$course$/top/A", "$course$/top/B").expect("Valid document"), 1);
+	// The changes are still waiting.
+	assert!(parser.get_current_content().contains("<text>First</text>"));
+	assert!(parser.get_current_content().contains("<text>$course$/top/A</text>"));
+
+	// Renaming the same category again conflicts with the registered rename.
+	match parser.rename_category("$course$/top/A", "$course$/top/C") {
+		Err(Error::OverlappingChange(_)) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	parser.try_execute_changes().expect("Valid changes");
+	assert_eq!(parser.get_current_content(), target_data);
+}
+
+/// Attributes can be added to and removed from existing elements, new ones
+/// use the same quotes as the existing ones.
+#[test]
+fn adding_and_removing_attributes() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <questiontext><text>Text</text></questiontext>
+    <input name='ans1' type='algebraic'><tans>ta</tans></input>
+    <tags/>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <questiontext format=\"html\"><text>Text</text></questiontext>
+    <input name='ans1' boxsize='&quot;15&quot;'><tans>ta</tans></input>
+    <tags empty=\"yes\"/>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["questiontext".to_string(), "input".to_string(), "tags".to_string()]);
+
+	parser.insert_attribute(&elements[0], "format", "html").expect("Valid target");
+	parser.remove_attribute(&elements[1], "type").expect("Valid target");
+	parser.insert_attribute(&elements[1], "boxsize", "\"15\"").expect("Valid target");
+	parser.insert_attribute(&elements[2], "empty", "yes").expect("Valid target");
+	match parser.insert_attribute(&elements[1], "name", "ans2") {
+		Err(Error::InvalidTarget(_)) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	match parser.remove_attribute(&elements[2], "missing") {
+		Err(Error::InvalidTarget(_)) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	parser.try_execute_changes().expect("Valid changes");
+	assert_eq!(parser.get_current_content(), target_data);
+}
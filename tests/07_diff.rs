@@ -0,0 +1,58 @@
+use position_preserving_moodle_question_xml_edit::*;
+
+/// Before executing or saving one may want to show what will change.
+/// The registered changes can be rendered as a unified diff.
+#[test]
+fn preview_pending_changes() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test question</text></name>
+    <first>Some</first>
+    <a>1</a>
+    <b>2</b>
+    <c>3</c>
+    <d>4</d>
+    <e>5</e>
+    <second>Thing</second>
+  </question>
+</quiz>
+".to_string();
+	let target_diff = "--- current
++++ pending
+@@ -3,5 +3,5 @@
+   <question type=\"some\">
+     <name><text>Test question</text></name>
+-    <first>Some</first>
++    <first>Other</first>
+     <a>1</a>
+     <b>2</b>
+@@ -9,5 +9,6 @@
+     <d>4</d>
+     <e>5</e>
+-    <second>Thing</second>
++    <second>Multi
++line</second>
+   </question>
+ </quiz>
+".to_string();
+	let mut parser = QParser::from_string(data.clone()).expect("Valid input should not fail");
+	assert_eq!(parser.pending_changes_diff(3), "".to_string());
+
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["first".to_string(), "second".to_string()]);
+	parser.register_change(Change::new(elements[0].clone().get_content().unwrap(), "Other".to_string()));
+	parser.register_change(Change::new(elements[1].clone().get_content().unwrap(), "Multi\nline".to_string()));
+
+	// Two lines of context, the changes are far enough from each other to be in separate hunks.
+	assert_eq!(parser.pending_changes_diff(2), target_diff);
+	// Nothing was executed.
+	assert_eq!(parser.get_current_content(), data);
+
+	// After executing the same diff is available against the original document.
+	parser.try_execute_changes().expect("Still valid");
+	assert_eq!(parser.pending_changes_diff(2), "".to_string());
+	assert_eq!(parser.diff_from_original(2), target_diff.replace("--- current\n+++ pending", "--- original\n+++ current"));
+
+	// With more context they merge to a single hunk.
+	assert!(parser.diff_from_original(3).starts_with("--- original\n+++ current\n@@ -2,12 +2,13 @@\n <quiz>\n"));
+}
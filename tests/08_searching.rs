@@ -0,0 +1,449 @@
+use position_preserving_moodle_question_xml_edit::*;
+
+/// Searches targeting a single question only parse that question, the
+/// positions they give are still positions in the whole document. Changing
+/// things inside questions does not require parsing the whole document again,
+/// adding questions does, but none of that is visible outside.
+#[test]
+fn searching_question_by_question() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 1  -->
+  <question type=\"category\">
+    <category><text>$course$/top</text></category>
+  </question>
+<!-- question: 2  -->
+  <question type=\"some\">
+    <name><text>First</text></name>
+    <foo bar=\"a > b\">x</foo>
+  </question>
+<!-- question: 3  -->
+  <question type=\"other\">
+    <name><text>Second</text></name>
+    <foo bar=\"c\">y</foo>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 1  -->
+  <question type=\"category\">
+    <category><text>$course$/top</text></category>
+  </question>
+<!-- question: 2  -->
+  <question type=\"some\">
+    <name><text>First</text></name>
+    <foo bar=\"a &gt; b, changed\">x, changed</foo>
+  </question>
+  <question type=\"new\">
+    <name><text>Third</text></name>
+  </question>
+<!-- question: 3  -->
+  <question type=\"other\">
+    <name><text>Second</text></name>
+    <foo bar=\"c, changed\">y, changed</foo>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+
+	for qnum in 0..2 {
+		let elements: Vec<ContentType> = parser.get_elements(qnum, vec!["foo".to_string()]);
+		if let ContentType::Element(_, _, parts) = &elements[0] {
+			if let ContentType::AttributeValue(_, attr) = &parts[0] {
+				let value: String = format!("{}, changed", attr.basic_entity_decode());
+				parser.register_change(Change::attribute_escaped_version(attr.clone(), value));
+			}
+			if let ContentType::ElementContent(content) = &parts[1] {
+				parser.register_change(Change::new(content.clone(), format!("{}, changed", content.content)));
+			}
+		} else {
+			panic!("Wrong type found!");
+		}
+	}
+
+	// A new question in the middle moves the later ones.
+	let questions: Vec<Question> = parser.find_questions();
+	let target: ContentType = ContentType::Element("question".to_string(), questions[0].whole_element.clone(), vec![]);
+	parser.insert_after(&target, "<question type=\"new\">\n  <name><text>Third</text></name>\n</question>".to_string()).expect("Valid target");
+	match parser.try_get_elements(1, vec!["name".to_string()]) {
+		Ok(elements) => {
+			if let ContentType::Element(_, whole, _) = &elements[0] {
+				assert_eq!(whole.content, "<name><text>Third</text></name>".to_string());
+			} else {
+				panic!("Wrong type found!");
+			}
+		},
+		Err(_) => {
+			panic!("Should have found the new question.");
+		}
+	}
+	assert_eq!(parser.get_current_content(), target_data);
+
+	match parser.try_get_elements(3, vec!["name".to_string()]) {
+		Err(Error::QuestionIndexOutOfRange { index, count }) => {
+			assert_eq!(index, 3);
+			assert_eq!(count, 3);
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+}
+
+/// Changes breaking a question are noticed even if only that question is checked.
+#[test]
+fn breaking_a_question() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>First</text></name>
+    <foo>x</foo>
+  </question>
+  <question type=\"some\">
+    <name><text>Second</text></name>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data.clone()).expect("Valid input should not fail");
+	let elements: Vec<ContentType> = parser.get_elements(0, vec!["foo".to_string()]);
+	let content = elements[0].clone().get_content().expect("Has content");
+	parser.register_change(Change::new(content, "<broken>".to_string()));
+	match parser.try_get_elements(1, vec!["name".to_string()]) {
+		Err(Error::BrokenChanges { offending, .. }) => {
+			assert_eq!(offending.len(), 1);
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	assert_eq!(parser.get_current_content(), data);
+}
+
+/// Selectors pick elements by their path and the values of their attributes and
+/// children, instead of just by their names.
+#[test]
+fn selecting_by_path() {
+	let mut parser = QParser::load_xml_file("tests/tests/minimal-stack.xml".to_string()).expect("Valid input should not fail");
+
+	let whole_of = |elements: Vec<ContentType>| -> Vec<String> {
+		let mut result: Vec<String> = Vec::new();
+		for element in elements {
+			match element {
+				ContentType::Element(_, whole, _) => result.push(whole.content),
+				ContentType::MoodleTextElement(name, _, _) => result.push(name),
+				_ => {
+					panic!("Wrong type found!");
+				}
+			}
+		}
+		result
+	};
+
+	let found: Vec<ContentType> = parser.select(0, "input[name=ans1]/tans").expect("Valid selector");
+	assert_eq!(whole_of(found), vec!["<tans>ta</tans>".to_string()]);
+
+	let found: Vec<ContentType> = parser.select(0, "prt[name='prt1']/node[0]/truefeedback/text").expect("Valid selector");
+	assert_eq!(whole_of(found), vec!["<text></text>".to_string()]);
+
+	// Without the leading slash the first step would also find the names of inputs and prts.
+	let found: Vec<ContentType> = parser.select(0, "/name/text").expect("Valid selector");
+	assert_eq!(whole_of(found), vec!["<text>minimal</text>".to_string()]);
+
+	// Descendants at any depth, in document order.
+	let found: Vec<ContentType> = parser.select(0, "//tans").expect("Valid selector");
+	assert_eq!(whole_of(found), vec!["<tans>ta</tans>".to_string(), "<tans>ta</tans>".to_string()]);
+	let found: Vec<ContentType> = parser.select(0, "*[name=ans1]").expect("Valid selector");
+	assert_eq!(found.len(), 2);
+
+	// Attribute predicates.
+	let found: Vec<ContentType> = parser.select(0, "questiontext[@format=html]").expect("Valid selector");
+	assert_eq!(whole_of(found), vec!["questiontext".to_string()]);
+	let found: Vec<ContentType> = parser.select(0, "questiontext[@format=\"moodle_auto_format\"]").expect("Valid selector");
+	assert_eq!(found.len(), 0);
+	let found: Vec<ContentType> = parser.select(0, "node/*[@format]").expect("Valid selector");
+	assert_eq!(whole_of(found), vec!["truefeedback".to_string(), "falsefeedback".to_string()]);
+
+	// Indices count from 0 and among the matches under the same parent, even after `//`.
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Test</text></name>
+    <a><n>1</n><n>2</n></a>
+    <b><n>3</n><c><n>4</n><n>5</n></c></b>
+  </question>
+</quiz>
+".to_string();
+	let mut nested = QParser::from_string(data).expect("Valid input should not fail");
+	let found: Vec<ContentType> = nested.select(0, "//n[0]").expect("Valid selector");
+	assert_eq!(whole_of(found), vec!["<n>1</n>".to_string(), "<n>3</n>".to_string(), "<n>4</n>".to_string()]);
+	let found: Vec<ContentType> = nested.select(0, "//n[1]").expect("Valid selector");
+	assert_eq!(whole_of(found), vec!["<n>2</n>".to_string(), "<n>5</n>".to_string()]);
+	let found: Vec<ContentType> = nested.select(0, "b//n[0]").expect("Valid selector");
+	assert_eq!(whole_of(found), vec!["<n>3</n>".to_string(), "<n>4</n>".to_string()]);
+
+	// Broken selectors are errors, not empty results.
+	for selector in ["", "prt[name=prt1", "prt//", "[0]", "input[name='ans1]", "input/@name"] {
+		match parser.select(0, selector) {
+			Err(Error::InvalidSelector(_)) => {},
+			_ => {
+				panic!("Wrong result for '{selector}'!");
+			}
+		}
+	}
+}
+
+/// Category-elements are not questions, but they can be listed and each question
+/// knows which category it goes to.
+#[test]
+fn categories() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+  <question type=\"some\">
+    <name><text>Before any category</text></name>
+  </question>
+  <question type=\"category\">
+    <category>
+      <text>$course$/top/First</text>
+    </category>
+    <info format=\"moodle_auto_format\">
+      <text>Described</text>
+    </info>
+    <idnumber>cat-1</idnumber>
+  </question>
+  <question>
+    <name><text>Typeless, ignored</text></name>
+  </question>
+  <question type=\"some\">
+    <name><text>In first</text></name>
+  </question>
+  <question type=\"category\">
+    <category><text>$course$/top/Second</text></category>
+  </question>
+  <question type=\"some\">
+    <name><text>In second</text></name>
+  </question>
+  <question type=\"other\">
+    <name><text>Also in second</text></name>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+
+	let categories: Vec<Category> = parser.find_categories().expect("Valid document");
+	assert_eq!(categories.len(), 2);
+	assert_eq!(categories[0].path.content, "$course$/top/First".to_string());
+	assert_eq!(categories[0].info.clone().expect("Has info").content, "Described".to_string());
+	assert_eq!(categories[0].idnumber.clone().expect("Has idnumber").content, "cat-1".to_string());
+	assert_eq!(categories[1].path.content, "$course$/top/Second".to_string());
+	assert!(categories[1].info.is_none());
+	assert!(categories[1].idnumber.is_none());
+
+	let questions: Vec<Question> = parser.find_questions();
+	let in_categories: Vec<Option<usize>> = questions.iter().map(|q| q.category).collect();
+	assert_eq!(in_categories, vec![None, Some(0), Some(1), Some(1)]);
+
+	// The references can be used to rename categories.
+	parser.register_change(Change::new(categories[1].path.clone(), "$course$/top/Renamed".to_string()));
+	let categories: Vec<Category> = parser.find_categories().expect("Valid document");
+	assert_eq!(categories[1].path.content, "$course$/top/Renamed".to_string());
+}
+
+/// Single questions can be found by name, name pattern, idnumber or the id in
+/// the comment Moodle writes before each question. Lookups matching no
+/// question or multiple questions are errors.
+#[test]
+fn looking_up_questions() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 0  -->
+  <question type=\"category\">
+    <category><text>$course$/top</text></category>
+  </question>
+<!-- question: 37922  -->
+  <question type=\"some\">
+    <name><text>Derivative &amp; chain rule</text></name>
+    <idnumber>D-1</idnumber>
+  </question>
+<!-- question: 37923  -->
+  <question type=\"some\">
+    <name><text><![CDATA[Derivative <b>2</b>]]></text></name>
+    <idnumber/>
+  </question>
+  <question type=\"other\">
+    <name><text>Integral</text></name>
+    <idnumber>I-1</idnumber>
+  </question>
+  <question type=\"other\">
+    <name><text>What is 2+3?</text></name>
+  </question>
+  <question type=\"other\">
+    <name><text>What is 2*3?</text></name>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+
+	let question: Question = parser.find_question_by_name("Derivative & chain rule").expect("Exists");
+	assert_eq!(question.index, 0);
+	assert_eq!(question.comment_id, Some(37922));
+	assert_eq!(parser.find_question_by_name("Derivative <b>?</b>").expect("Exists").index, 1);
+	assert_eq!(parser.find_question_by_name("*gral").expect("Exists").index, 2);
+	assert_eq!(parser.find_question_by_idnumber("I-1").expect("Exists").index, 2);
+	assert_eq!(parser.find_question_by_comment_id(37923).expect("Exists").index, 1);
+	assert_eq!(parser.find_questions()[2].comment_id, None);
+
+	match parser.find_question_by_name("Derivative*") {
+		Err(Error::AmbiguousQuestion { indices, .. }) => {
+			assert_eq!(indices, vec![0, 1]);
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	// Names with pattern characters in them can be matched exactly.
+	match parser.find_question_by_name("What is 2*3?") {
+		Err(Error::AmbiguousQuestion { indices, .. }) => {
+			assert_eq!(indices, vec![3, 4]);
+		},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	assert_eq!(parser.find_question_by_exact_name("What is 2*3?").expect("Exists").index, 4);
+	match parser.find_question_by_exact_name("Derivative*") {
+		Err(Error::NoSuchQuestion(_)) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	match parser.find_question_by_name("Derivative") {
+		Err(Error::NoSuchQuestion(_)) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	match parser.find_question_by_comment_id(0) {
+		Err(Error::NoSuchQuestion(_)) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+	match parser.find_question_by_idnumber("") {
+		Err(Error::NoSuchQuestion(_)) => {},
+		_ => {
+			panic!("Wrong result!");
+		}
+	}
+}
+
+/// Comments and processing instructions can be found per question or in the
+/// whole document, and changed, added and removed like other content.
+#[test]
+fn comments_and_processing_instructions() {
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 12  -->
+  <question type=\"some\">
+    <name><text>First</text></name>
+    <!-- Review: check the feedback -->
+    <?review status=\"open\"?>
+    <generalfeedback format=\"html\"><text></text></generalfeedback>
+  </question>
+<!-- question: 13  -->
+  <question type=\"some\">
+    <name><text>Second</text></name>
+  </question>
+</quiz>
+".to_string();
+	let target_data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 12  -->
+  <question type=\"some\">
+    <name><text>First</text></name>
+    <!-- Review: fixed - - see the log -->
+    <generalfeedback format=\"html\"><text></text></generalfeedback>
+  </question>
+<!-- question: 13  -->
+  <question type=\"some\">
+    <!-- Review: new -->
+    <name><text>Second</text></name>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+
+	let comments: Vec<ContentType> = parser.get_comments(0).expect("Valid question");
+	assert_eq!(comments.len(), 2);
+	if let ContentType::Comment(whole, text) = &comments[0] {
+		assert_eq!(whole.content, "<!-- Review: check the feedback -->".to_string());
+		assert_eq!(text.content, " Review: check the feedback ".to_string());
+		parser.register_change(Change::comment_escaped_version(text.clone(), " Review: fixed -- see the log ".to_string()));
+	} else {
+		panic!("Wrong type found!");
+	}
+	if let ContentType::ProcessingInstruction(target, whole, value) = &comments[1] {
+		assert_eq!(target, "review");
+		assert_eq!(value.content, "status=\"open\"".to_string());
+		parser.remove_element(whole).expect("Valid target");
+	} else {
+		panic!("Wrong type found!");
+	}
+	assert_eq!(parser.get_comments(1).expect("Valid question").len(), 0);
+
+	let name: Vec<ContentType> = parser.get_elements(1, vec!["name".to_string()]);
+	parser.insert_before(&name[0], "<!-- Review: new -->".to_string()).expect("Valid target");
+
+	let comments: Vec<ContentType> = parser.get_document_comments().expect("Valid document");
+	let texts: Vec<String> = comments.into_iter().map(|c| c.get_content().expect("Has content").content).collect();
+	assert_eq!(texts, vec![" question: 12  ", " Review: fixed - - see the log ", " question: 13  ", " Review: new "]);
+	assert_eq!(parser.get_current_content(), target_data);
+}
+
+/// References can be turned into lines and columns for reporting, along with
+/// the question they are in.
+#[test]
+fn locating_references() {
+	let mut parser = QParser::load_xml_file("tests/tests/minimal-stack.xml".to_string()).expect("Valid input should not fail");
+	let found: Vec<ContentType> = parser.select(0, "prt[name=prt1]/node[0]/sans").expect("Valid selector");
+	let sans = found[0].clone().get_content().expect("Has content");
+	let location: Location = parser.locate(&sans).expect("Current reference");
+	assert_eq!(location.start.line, 87);
+	assert_eq!(location.start.column, 15);
+	assert_eq!(location.end.line, 87);
+	assert_eq!(location.end.column, 19);
+	assert_eq!(location.question, Some(0));
+	assert_eq!(location.question_name, Some("minimal".to_string()));
+
+	let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<quiz>
+<!-- question: 1  -->
+  <question type=\"some\">
+    <name><text>P&#xE4;&#xE4;</text></name>
+    <foo>ää</foo><bar>x</bar>
+  </question>
+</quiz>
+".to_string();
+	let mut parser = QParser::from_string(data).expect("Valid input should not fail");
+	let comments: Vec<ContentType> = parser.get_document_comments().expect("Valid document");
+	let comment = comments[0].clone().get_content().expect("Has content");
+	let bar = parser.get_elements(0, vec!["bar".to_string()])[0].clone().get_content().expect("Has content");
+
+	// Columns are counted both in characters and in bytes.
+	let location: Location = parser.locate(&bar).expect("Current reference");
+	assert_eq!(location.start, TextPosition { offset: bar.range().start, line: 6, column: 23, byte_column: 25 });
+	assert_eq!(location.question_name, Some("Pää".to_string()));
+
+	// Things outside questions are not in any question.
+	let location: Location = parser.locate(&comment).expect("Current reference");
+	assert_eq!(location.start.line, 3);
+	assert_eq!(location.question, None);
+
+	// Old references get rebased.
+	let foo = parser.get_elements(0, vec!["foo".to_string()])[0].clone().get_content().expect("Has content");
+	parser.register_change(Change::new(foo, "a\nb".to_string()));
+	parser.try_execute_changes().expect("Valid changes");
+	let location: Location = parser.locate(&bar).expect("Untouched reference");
+	assert_eq!(location.start.line, 7);
+	assert_eq!(location.start.column, 13);
+}